@@ -0,0 +1 @@
+DROP TABLE cart_items;
\ No newline at end of file
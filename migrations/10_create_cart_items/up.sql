@@ -0,0 +1,6 @@
+CREATE TABLE cart_items (
+  user_id INT NOT NULL REFERENCES users(id),
+  product_id INT NOT NULL REFERENCES products(id),
+  quantity INT NOT NULL CHECK (quantity > 0),
+  PRIMARY KEY(user_id, product_id)
+);
\ No newline at end of file
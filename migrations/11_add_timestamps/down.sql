@@ -0,0 +1,34 @@
+DROP TRIGGER IF EXISTS set_updated_at ON cart_items;
+ALTER TABLE cart_items
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
+
+DROP TRIGGER IF EXISTS set_updated_at ON orders_products;
+ALTER TABLE orders_products
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
+
+DROP TRIGGER IF EXISTS set_updated_at ON orders;
+ALTER TABLE orders
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
+
+DROP TRIGGER IF EXISTS set_updated_at ON campaigns;
+ALTER TABLE campaigns
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
+
+DROP TRIGGER IF EXISTS set_updated_at ON products;
+ALTER TABLE products
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
+
+DROP TRIGGER IF EXISTS set_updated_at ON categories;
+ALTER TABLE categories
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
+
+DROP TRIGGER IF EXISTS set_updated_at ON users;
+ALTER TABLE users
+  DROP COLUMN created_at,
+  DROP COLUMN updated_at;
\ No newline at end of file
@@ -0,0 +1,34 @@
+ALTER TABLE users
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('users');
+
+ALTER TABLE categories
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('categories');
+
+ALTER TABLE products
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('products');
+
+ALTER TABLE campaigns
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('campaigns');
+
+ALTER TABLE orders
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('orders');
+
+ALTER TABLE orders_products
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('orders_products');
+
+ALTER TABLE cart_items
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('cart_items');
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE orders
+  DROP COLUMN subtotal,
+  DROP COLUMN shipping_fee,
+  DROP COLUMN discount_amount,
+  DROP COLUMN pricing_rules;
\ No newline at end of file
@@ -0,0 +1,32 @@
+ALTER TABLE orders
+  ADD COLUMN subtotal FLOAT NOT NULL DEFAULT 0,
+  ADD COLUMN shipping_fee FLOAT NOT NULL DEFAULT 0,
+  ADD COLUMN discount_amount FLOAT NOT NULL DEFAULT 0,
+  ADD COLUMN pricing_rules JSONB NOT NULL DEFAULT '{}';
+
+UPDATE orders
+SET shipping_fee = CASE WHEN lines.subtotal < 150 THEN 35 ELSE 0 END
+FROM (
+  SELECT orders_products.order_id, SUM(products.list_price * orders_products.quantity) AS subtotal
+  FROM orders_products
+  INNER JOIN products ON products.id = orders_products.product_id
+  GROUP BY orders_products.order_id
+) AS lines
+WHERE lines.order_id = orders.id;
+
+UPDATE orders
+SET subtotal = price_without_discount - shipping_fee,
+    discount_amount = price_without_discount - discounted_price,
+    pricing_rules = jsonb_build_object(
+      'shipping_cost', 35,
+      'free_shipping_threshold', 150,
+      'campaign', (
+        SELECT to_jsonb(campaign_rules)
+        FROM (
+          SELECT id, description, min_purchase_price, min_purchase_quantity,
+                 discount_quantity, discount_percent, rule_author, rule_category
+          FROM campaigns
+          WHERE campaigns.id = orders.campaign_id
+        ) AS campaign_rules
+      )
+    );
\ No newline at end of file
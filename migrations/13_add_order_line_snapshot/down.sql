@@ -0,0 +1,5 @@
+ALTER TABLE orders_products
+  DROP COLUMN unit_price,
+  DROP COLUMN title,
+  DROP COLUMN author,
+  DROP COLUMN category_title;
\ No newline at end of file
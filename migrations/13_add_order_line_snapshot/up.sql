@@ -0,0 +1,20 @@
+ALTER TABLE orders_products
+  ADD COLUMN unit_price FLOAT,
+  ADD COLUMN title VARCHAR,
+  ADD COLUMN author VARCHAR,
+  ADD COLUMN category_title VARCHAR;
+
+UPDATE orders_products
+SET unit_price = products.list_price,
+    title = products.title,
+    author = products.author,
+    category_title = categories.title
+FROM products
+INNER JOIN categories ON categories.id = products.category_id
+WHERE products.id = orders_products.product_id;
+
+ALTER TABLE orders_products
+  ALTER COLUMN unit_price SET NOT NULL,
+  ALTER COLUMN title SET NOT NULL,
+  ALTER COLUMN author SET NOT NULL,
+  ALTER COLUMN category_title SET NOT NULL;
\ No newline at end of file
@@ -0,0 +1,15 @@
+ALTER TABLE orders_products
+  ALTER COLUMN unit_price TYPE FLOAT;
+
+ALTER TABLE orders
+  ALTER COLUMN price_without_discount TYPE FLOAT,
+  ALTER COLUMN discounted_price TYPE FLOAT,
+  ALTER COLUMN subtotal TYPE FLOAT,
+  ALTER COLUMN shipping_fee TYPE FLOAT,
+  ALTER COLUMN discount_amount TYPE FLOAT;
+
+ALTER TABLE campaigns
+  ALTER COLUMN min_purchase_price TYPE FLOAT;
+
+ALTER TABLE products
+  ALTER COLUMN list_price TYPE FLOAT;
\ No newline at end of file
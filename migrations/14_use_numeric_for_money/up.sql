@@ -0,0 +1,15 @@
+ALTER TABLE products
+  ALTER COLUMN list_price TYPE NUMERIC(12, 2) USING ROUND(list_price::NUMERIC, 2);
+
+ALTER TABLE campaigns
+  ALTER COLUMN min_purchase_price TYPE NUMERIC(12, 2) USING ROUND(min_purchase_price::NUMERIC, 2);
+
+ALTER TABLE orders
+  ALTER COLUMN price_without_discount TYPE NUMERIC(12, 2) USING ROUND(price_without_discount::NUMERIC, 2),
+  ALTER COLUMN discounted_price TYPE NUMERIC(12, 2) USING ROUND(discounted_price::NUMERIC, 2),
+  ALTER COLUMN subtotal TYPE NUMERIC(12, 2) USING ROUND(subtotal::NUMERIC, 2),
+  ALTER COLUMN shipping_fee TYPE NUMERIC(12, 2) USING ROUND(shipping_fee::NUMERIC, 2),
+  ALTER COLUMN discount_amount TYPE NUMERIC(12, 2) USING ROUND(discount_amount::NUMERIC, 2);
+
+ALTER TABLE orders_products
+  ALTER COLUMN unit_price TYPE NUMERIC(12, 2) USING ROUND(unit_price::NUMERIC, 2);
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE orders
+  DROP COLUMN shipping_method;
+
+ALTER TABLE products
+  DROP COLUMN weight_grams;
+
+DROP TABLE shipping_methods;
\ No newline at end of file
@@ -0,0 +1,25 @@
+CREATE TABLE shipping_methods (
+  id SERIAL PRIMARY KEY,
+  code VARCHAR NOT NULL UNIQUE,
+  name VARCHAR NOT NULL,
+  base_fee NUMERIC(12, 2) NOT NULL DEFAULT 0,
+  per_item_fee NUMERIC(12, 2) NOT NULL DEFAULT 0,
+  per_kg_fee NUMERIC(12, 2) NOT NULL DEFAULT 0,
+  free_shipping_threshold NUMERIC(12, 2),
+  is_active BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+SELECT diesel_manage_updated_at('shipping_methods');
+
+INSERT INTO shipping_methods (code, name, base_fee, per_item_fee, per_kg_fee, free_shipping_threshold)
+VALUES
+  ('standard', 'Standard shipping', 35, 0, 0, 150),
+  ('express', 'Express shipping', 60, 5, 0, NULL),
+  ('pickup', 'Store pickup', 0, 0, 0, NULL);
+
+ALTER TABLE products
+  ADD COLUMN weight_grams INT NOT NULL DEFAULT 0 CHECK (weight_grams >= 0);
+
+ALTER TABLE orders
+  ADD COLUMN shipping_method VARCHAR NOT NULL DEFAULT 'standard';
\ No newline at end of file
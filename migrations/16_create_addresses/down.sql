@@ -0,0 +1,5 @@
+ALTER TABLE orders
+  DROP COLUMN shipping_address,
+  DROP COLUMN billing_address;
+
+DROP TABLE addresses;
\ No newline at end of file
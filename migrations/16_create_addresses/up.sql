@@ -0,0 +1,20 @@
+CREATE TABLE addresses (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  full_name VARCHAR NOT NULL,
+  line1 VARCHAR NOT NULL,
+  line2 VARCHAR,
+  city VARCHAR NOT NULL,
+  state VARCHAR,
+  postal_code VARCHAR NOT NULL,
+  country VARCHAR NOT NULL,
+  phone VARCHAR,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+CREATE INDEX addresses_user_id_idx ON addresses (user_id);
+SELECT diesel_manage_updated_at('addresses');
+
+ALTER TABLE orders
+  ADD COLUMN shipping_address JSONB,
+  ADD COLUMN billing_address JSONB;
\ No newline at end of file
@@ -0,0 +1,6 @@
+DROP TABLE order_return_items;
+
+DROP TABLE order_returns;
+
+ALTER TABLE orders_products
+  DROP COLUMN returned_quantity;
\ No newline at end of file
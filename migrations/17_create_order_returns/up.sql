@@ -0,0 +1,25 @@
+ALTER TABLE orders_products
+  ADD COLUMN returned_quantity INT NOT NULL DEFAULT 0
+  CHECK (returned_quantity >= 0 AND returned_quantity <= quantity);
+
+CREATE TABLE order_returns (
+  id SERIAL PRIMARY KEY,
+  order_id INT NOT NULL REFERENCES orders(id),
+  status VARCHAR NOT NULL DEFAULT 'requested'
+  CHECK (status IN ('requested', 'approved', 'rejected')),
+  reason VARCHAR,
+  resolution_note VARCHAR,
+  refund_amount NUMERIC(12, 2),
+  resolved_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+CREATE INDEX order_returns_order_id_idx ON order_returns (order_id);
+SELECT diesel_manage_updated_at('order_returns');
+
+CREATE TABLE order_return_items (
+  order_return_id INT NOT NULL REFERENCES order_returns(id) ON DELETE CASCADE,
+  product_id INT NOT NULL REFERENCES products(id),
+  quantity INT NOT NULL CHECK (quantity > 0),
+  PRIMARY KEY(order_return_id, product_id)
+);
\ No newline at end of file
@@ -0,0 +1,7 @@
+DROP TABLE notifications;
+DROP TABLE user_order_stats;
+
+UPDATE orders SET status = 'pending' WHERE status = 'processing';
+ALTER TABLE orders DROP CONSTRAINT orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check
+  CHECK (status IN ('pending', 'paid', 'packed', 'shipped', 'delivered', 'cancelled', 'refunded'));
\ No newline at end of file
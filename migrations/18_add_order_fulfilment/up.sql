@@ -0,0 +1,29 @@
+ALTER TABLE orders DROP CONSTRAINT orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check
+  CHECK (status IN ('processing', 'pending', 'paid', 'packed', 'shipped', 'delivered', 'cancelled', 'refunded'));
+
+CREATE TABLE user_order_stats (
+  user_id INT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+  order_count INT NOT NULL DEFAULT 0,
+  total_spent NUMERIC(12, 2) NOT NULL DEFAULT 0,
+  last_order_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+SELECT diesel_manage_updated_at('user_order_stats');
+
+INSERT INTO user_order_stats (user_id, order_count, total_spent, last_order_at)
+SELECT user_id, COUNT(*), SUM(discounted_price), MAX(created_at)
+FROM orders
+GROUP BY user_id;
+
+CREATE TABLE notifications (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  order_id INT REFERENCES orders(id),
+  kind VARCHAR NOT NULL,
+  subject VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+CREATE INDEX notifications_user_id_idx ON notifications (user_id);
\ No newline at end of file
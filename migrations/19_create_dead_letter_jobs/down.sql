@@ -0,0 +1 @@
+DROP TABLE dead_letter_jobs;
\ No newline at end of file
@@ -0,0 +1,15 @@
+CREATE TABLE dead_letter_jobs (
+  id SERIAL PRIMARY KEY,
+  job_name VARCHAR NOT NULL,
+  job_id VARCHAR NOT NULL,
+  payload JSONB NOT NULL,
+  error TEXT NOT NULL,
+  attempts INT NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'dead'
+  CHECK (status IN ('dead', 'retried', 'discarded')),
+  resolved_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+CREATE INDEX dead_letter_jobs_status_idx ON dead_letter_jobs (status);
+SELECT diesel_manage_updated_at('dead_letter_jobs');
\ No newline at end of file
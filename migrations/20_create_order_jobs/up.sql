@@ -0,0 +1,13 @@
+CREATE TABLE order_jobs (
+  id SERIAL PRIMARY KEY,
+  payload JSONB NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'queued'
+  CHECK (status IN ('queued', 'running', 'succeeded', 'failed')),
+  order_id INT REFERENCES orders(id) ON DELETE SET NULL,
+  error JSONB,
+  started_at TIMESTAMP,
+  finished_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+SELECT diesel_manage_updated_at('order_jobs');
\ No newline at end of file
@@ -0,0 +1,13 @@
+CREATE TABLE outbox_messages (
+  id SERIAL PRIMARY KEY,
+  job_name VARCHAR NOT NULL,
+  payload JSONB NOT NULL,
+  attempts INT NOT NULL DEFAULT 0,
+  last_error TEXT,
+  locked_until TIMESTAMP,
+  delivered_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+CREATE INDEX outbox_messages_undelivered_idx ON outbox_messages (id) WHERE delivered_at IS NULL;
+SELECT diesel_manage_updated_at('outbox_messages');
\ No newline at end of file
@@ -0,0 +1,33 @@
+CREATE TABLE webhook_subscriptions (
+  id SERIAL PRIMARY KEY,
+  url VARCHAR NOT NULL,
+  event_types TEXT[] NOT NULL,
+  secret VARCHAR NOT NULL,
+  is_active BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+SELECT diesel_manage_updated_at('webhook_subscriptions');
+
+CREATE TABLE webhook_events (
+  id SERIAL PRIMARY KEY,
+  event_type VARCHAR NOT NULL,
+  payload JSONB NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE webhook_deliveries (
+  id SERIAL PRIMARY KEY,
+  webhook_subscription_id INT NOT NULL REFERENCES webhook_subscriptions(id) ON DELETE CASCADE,
+  webhook_event_id INT NOT NULL REFERENCES webhook_events(id) ON DELETE CASCADE,
+  status VARCHAR NOT NULL DEFAULT 'pending'
+  CHECK (status IN ('pending', 'succeeded', 'failed')),
+  attempts INT NOT NULL DEFAULT 0,
+  response_status INT,
+  last_error TEXT,
+  delivered_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+CREATE INDEX webhook_deliveries_webhook_subscription_id_idx ON webhook_deliveries (webhook_subscription_id);
+SELECT diesel_manage_updated_at('webhook_deliveries');
\ No newline at end of file
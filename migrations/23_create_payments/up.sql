@@ -0,0 +1,16 @@
+CREATE TABLE payments (
+  id SERIAL PRIMARY KEY,
+  order_id INT NOT NULL UNIQUE REFERENCES orders(id) ON DELETE CASCADE,
+  gateway VARCHAR NOT NULL,
+  payment_method VARCHAR,
+  reference VARCHAR NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'authorized'
+  CHECK (status IN ('authorized', 'captured', 'voided', 'refunded')),
+  amount NUMERIC(12, 2) NOT NULL,
+  captured_amount NUMERIC(12, 2) NOT NULL DEFAULT 0,
+  refunded_amount NUMERIC(12, 2) NOT NULL DEFAULT 0
+  CHECK (refunded_amount <= captured_amount),
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+SELECT diesel_manage_updated_at('payments');
\ No newline at end of file
@@ -0,0 +1,5 @@
+UPDATE orders SET status = 'pending' WHERE status = 'review';
+ALTER TABLE orders DROP CONSTRAINT orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check
+  CHECK (status IN ('processing', 'pending', 'paid', 'packed', 'shipped', 'delivered', 'cancelled', 'refunded'));
+ALTER TABLE orders DROP COLUMN risk_flags;
\ No newline at end of file
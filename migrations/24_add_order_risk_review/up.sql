@@ -0,0 +1,4 @@
+ALTER TABLE orders ADD COLUMN risk_flags JSONB;
+ALTER TABLE orders DROP CONSTRAINT orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check
+  CHECK (status IN ('processing', 'review', 'pending', 'paid', 'packed', 'shipped', 'delivered', 'cancelled', 'refunded'));
\ No newline at end of file
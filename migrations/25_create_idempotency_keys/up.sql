@@ -0,0 +1,8 @@
+CREATE TABLE idempotency_keys (
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  idempotency_key VARCHAR NOT NULL,
+  fingerprint VARCHAR NOT NULL,
+  response JSONB NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, idempotency_key)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE orders_products DROP COLUMN quantity;
\ No newline at end of file
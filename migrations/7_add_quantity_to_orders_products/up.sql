@@ -0,0 +1,2 @@
+ALTER TABLE orders_products
+  ADD COLUMN quantity INT NOT NULL DEFAULT 1 CHECK (quantity > 0);
\ No newline at end of file
@@ -0,0 +1,14 @@
+ALTER TABLE orders
+  ADD COLUMN status VARCHAR NOT NULL DEFAULT 'pending'
+  CHECK (status IN ('pending', 'paid', 'packed', 'shipped', 'delivered', 'cancelled', 'refunded'));
+
+CREATE TABLE order_status_history (
+  id SERIAL PRIMARY KEY,
+  order_id INT NOT NULL REFERENCES orders(id),
+  from_status VARCHAR,
+  to_status VARCHAR NOT NULL,
+  changed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO order_status_history (order_id, to_status)
+SELECT id, status FROM orders;
\ No newline at end of file
@@ -0,0 +1,4 @@
+ALTER TABLE orders
+  DROP COLUMN cancelled_by,
+  DROP COLUMN cancellation_reason,
+  DROP COLUMN cancelled_at;
\ No newline at end of file
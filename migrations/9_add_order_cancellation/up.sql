@@ -0,0 +1,4 @@
+ALTER TABLE orders
+  ADD COLUMN cancelled_by VARCHAR,
+  ADD COLUMN cancellation_reason VARCHAR,
+  ADD COLUMN cancelled_at TIMESTAMP;
\ No newline at end of file
@@ -1,6 +1,7 @@
 use diesel::insert_into;
 use diesel::prelude::*;
 use rust_order_api::establish_connection;
+use rust_order_api::models::Money;
 use rust_order_api::schema;
 use schema::campaigns;
 use schema::campaigns::dsl::*;
@@ -17,7 +18,7 @@ use std::io::Read;
 #[derive(Deserialize, Insertable)]
 struct Campaign {
     description: String,
-    min_purchase_price: Option<f64>,
+    min_purchase_price: Option<Money>,
     min_purchase_quantity: Option<i32>,
     discount_quantity: Option<i32>,
     discount_percent: Option<i32>,
@@ -35,7 +36,7 @@ struct Product {
     title: String,
     category_id: i32,
     author: String,
-    list_price: f64,
+    list_price: Money,
     stock_quantity: i32,
 }
 
@@ -0,0 +1,84 @@
+use actix_web::{post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use dotenvy::dotenv;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Local stand-in for a webhook receiver. Prints every delivery and checks
+/// its signature against WEBHOOK_SECRET. The first WEBHOOK_RECEIVER_FAILURES
+/// requests are answered with 500 to exercise the retries.
+struct Receiver {
+    secret: Option<String>,
+    failures: usize,
+    received: AtomicUsize,
+}
+
+fn is_valid_signature(secret: &str, body: &str, signature: &str) -> bool {
+    let Some(signature) = signature
+        .strip_prefix("sha256=")
+        .and_then(|signature| hex::decode(signature).ok())
+    else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[post("/{path:.*}")]
+async fn receive(req: HttpRequest, body: String, receiver: web::Data<Receiver>) -> impl Responder {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_owned()
+    };
+    let received = receiver.received.fetch_add(1, Ordering::SeqCst) + 1;
+    let signature = header("X-Webhook-Signature");
+    let signature_check = match &receiver.secret {
+        Some(secret) if is_valid_signature(secret, &body, &signature) => "valid",
+        Some(_) => "invalid",
+        None => "not checked",
+    };
+    println!(
+        "#{} {} delivery {} signature {}\n{}",
+        received,
+        header("X-Webhook-Event"),
+        header("X-Webhook-Delivery"),
+        signature_check,
+        body
+    );
+
+    if signature_check == "invalid" {
+        return HttpResponse::Unauthorized().finish();
+    }
+    if received <= receiver.failures {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenv().ok();
+    let port = env::var("WEBHOOK_RECEIVER_PORT")
+        .ok()
+        .and_then(|value| value.parse::<u16>().ok())
+        .unwrap_or(3001);
+    let receiver = web::Data::new(Receiver {
+        secret: env::var("WEBHOOK_SECRET").ok(),
+        failures: env::var("WEBHOOK_RECEIVER_FAILURES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0),
+        received: AtomicUsize::new(0),
+    });
+    println!("Listening for webhooks on http://127.0.0.1:{}", port);
+    HttpServer::new(move || App::new().app_data(receiver.clone()).service(receive))
+        .bind(("127.0.0.1", port))?
+        .run()
+        .await
+}
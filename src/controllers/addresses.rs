@@ -0,0 +1,235 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::users::ensure_user_exists;
+use crate::insertables::NewAddress;
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder, Result};
+use diesel::{prelude::*, r2d2};
+use rust_order_api::models::Address;
+use rust_order_api::schema::{self, addresses};
+use serde::Deserialize;
+use serde_json::{json, Value};
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = addresses)]
+#[diesel(treat_none_as_null = true)]
+pub struct AddressDto {
+    pub full_name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub state: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+    pub phone: Option<String>,
+}
+
+fn validate_address(address: &AddressDto) -> Result<(), DbError> {
+    let required_fields = [
+        ("full_name", &address.full_name),
+        ("line1", &address.line1),
+        ("city", &address.city),
+        ("postal_code", &address.postal_code),
+        ("country", &address.country),
+    ];
+    for (field, value) in required_fields {
+        if value.trim().is_empty() {
+            return Err(
+                ApiError::BadRequest(format!("Address {} must not be empty", field)).into(),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn get_address_snapshot(address: &Address) -> Value {
+    json!({
+        "id": address.id,
+        "full_name": address.full_name,
+        "line1": address.line1,
+        "line2": address.line2,
+        "city": address.city,
+        "state": address.state,
+        "postal_code": address.postal_code,
+        "country": address.country,
+        "phone": address.phone,
+    })
+}
+
+pub fn get_all_addresses(conn: &mut PgConnection, _user_id: i32) -> Result<Vec<Address>, DbError> {
+    use schema::addresses::dsl::*;
+    ensure_user_exists(conn, _user_id)?;
+    let all_addresses = addresses
+        .filter(user_id.eq(_user_id))
+        .select(Address::as_select())
+        .order(id)
+        .load(conn)?;
+    Ok(all_addresses)
+}
+
+pub fn get_address_by_id(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    address_id: i32,
+) -> Result<Address, DbError> {
+    use schema::addresses::dsl::*;
+    let address = addresses
+        .filter(id.eq(address_id))
+        .filter(user_id.eq(_user_id))
+        .select(Address::as_select())
+        .first::<Address>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Address {} not found for user {}",
+                address_id, _user_id
+            ))
+        })?;
+    Ok(address)
+}
+
+pub fn insert_new_address(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    address: AddressDto,
+) -> Result<Address, DbError> {
+    use schema::addresses::dsl::*;
+    ensure_user_exists(conn, _user_id)?;
+    validate_address(&address)?;
+    let new_address = NewAddress {
+        user_id: _user_id,
+        full_name: address.full_name,
+        line1: address.line1,
+        line2: address.line2,
+        city: address.city,
+        state: address.state,
+        postal_code: address.postal_code,
+        country: address.country,
+        phone: address.phone,
+    };
+    let created_address = diesel::insert_into(addresses)
+        .values(&new_address)
+        .returning(Address::as_returning())
+        .get_result(conn)?;
+    Ok(created_address)
+}
+
+pub fn update_address_by_id(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    address_id: i32,
+    address: &AddressDto,
+) -> Result<Address, DbError> {
+    use schema::addresses::dsl::*;
+    validate_address(address)?;
+    let updated_address = diesel::update(
+        addresses
+            .filter(id.eq(address_id))
+            .filter(user_id.eq(_user_id)),
+    )
+    .set(address)
+    .returning(Address::as_returning())
+    .get_result(conn)
+    .optional()?
+    .ok_or_else(|| {
+        ApiError::NotFound(format!(
+            "Address {} not found for user {}",
+            address_id, _user_id
+        ))
+    })?;
+    Ok(updated_address)
+}
+
+pub fn delete_address_by_id(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    address_id: i32,
+) -> Result<String, DbError> {
+    use schema::addresses::dsl::*;
+    let deleted = diesel::delete(
+        addresses
+            .filter(id.eq(address_id))
+            .filter(user_id.eq(_user_id)),
+    )
+    .execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Address {} not found for user {}",
+            address_id, _user_id
+        ))
+        .into());
+    }
+    Ok("Address deleted".to_string())
+}
+
+#[get("/api/users/{user_id}/addresses")]
+async fn get_addresses(pool: web::Data<DbPool>, user_id: web::Path<i32>) -> Result<impl Responder> {
+    let all_addresses = web::block(move || {
+        let mut conn = pool.get()?;
+        get_all_addresses(&mut conn, *user_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(all_addresses))
+}
+
+#[get("/api/users/{user_id}/addresses/{address_id}")]
+async fn get_address(
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, i32)>,
+) -> Result<impl Responder> {
+    let (_user_id, address_id) = path.into_inner();
+    let address = web::block(move || {
+        let mut conn = pool.get()?;
+        get_address_by_id(&mut conn, _user_id, address_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(address))
+}
+
+#[post("/api/users/{user_id}/addresses")]
+async fn create_address(
+    pool: web::Data<DbPool>,
+    user_id: web::Path<i32>,
+    form: web::Json<AddressDto>,
+) -> Result<impl Responder> {
+    let address = web::block(move || {
+        let mut conn = pool.get()?;
+        insert_new_address(&mut conn, *user_id, form.into_inner())
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Created().json(address))
+}
+
+#[put("/api/users/{user_id}/addresses/{address_id}")]
+async fn update_address(
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, i32)>,
+    form: web::Json<AddressDto>,
+) -> Result<impl Responder> {
+    let (_user_id, address_id) = path.into_inner();
+    let address = web::block(move || {
+        let mut conn = pool.get()?;
+        update_address_by_id(&mut conn, _user_id, address_id, &form)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(address))
+}
+
+#[delete("/api/users/{user_id}/addresses/{address_id}")]
+async fn delete_address(
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, i32)>,
+) -> Result<impl Responder> {
+    let (_user_id, address_id) = path.into_inner();
+    let address = web::block(move || {
+        let mut conn = pool.get()?;
+        delete_address_by_id(&mut conn, _user_id, address_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(address))
+}
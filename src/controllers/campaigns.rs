@@ -1,6 +1,7 @@
 use crate::insertables::NewCampaign;
 use actix_web::{delete, error, get, post, web, HttpResponse, Responder, Result};
 use diesel::{prelude::*, r2d2};
+use r2d2_redis::redis;
 use rust_order_api::models::Campaign;
 use rust_order_api::schema;
 use schema::campaigns::dsl::*;
@@ -23,25 +24,31 @@ pub fn get_campaign_by_id(conn: &mut PgConnection, campaign_id: i32) -> Result<C
     Ok(campaign)
 }
 
+pub fn get_cached_campaigns(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+) -> Result<Vec<Campaign>, DbError> {
+    let campaigns_result: Option<String> = redis::cmd("GET").arg("campaigns").query(redis_conn)?;
+
+    match campaigns_result.and_then(|data| serde_json::from_str::<Vec<Campaign>>(&data).ok()) {
+        Some(cached_campaigns) => Ok(cached_campaigns),
+        None => {
+            let all_campaigns = campaigns.select(Campaign::as_select()).load(conn)?;
+            let _: () = redis::cmd("SET")
+                .arg("campaigns")
+                .arg(serde_json::to_string(&all_campaigns)?)
+                .arg("EX")
+                .arg(30)
+                .query(redis_conn)?;
+            Ok(all_campaigns)
+        }
+    }
+}
+
 pub fn insert_new_campaign(
     conn: &mut PgConnection,
-    _description: &str,
-    _min_purchase_price: f64,
-    _min_purchase_quantity: i32,
-    _discount_quantity: i32,
-    _discount_percent: i32,
-    _rule_author: &str,
-    _rule_category: &str,
+    new_campaign: NewCampaign,
 ) -> Result<NewCampaign, DbError> {
-    let new_campaign = NewCampaign {
-        description: _description.to_owned(),
-        min_purchase_price: _min_purchase_price.to_owned(),
-        min_purchase_quantity: _min_purchase_quantity.to_owned(),
-        discount_quantity: _discount_quantity.to_owned(),
-        discount_percent: _discount_percent.to_owned(),
-        rule_author: _rule_author.to_owned(),
-        rule_category: _rule_category.to_owned(),
-    };
     diesel::insert_into(campaigns)
         .values(&new_campaign)
         .execute(conn)?;
@@ -85,16 +92,7 @@ async fn create_campaign(
 ) -> Result<impl Responder> {
     let campaign = web::block(move || {
         let mut conn = pool.get()?;
-        insert_new_campaign(
-            &mut conn,
-            &form.description,
-            form.min_purchase_price,
-            form.min_purchase_quantity,
-            form.discount_quantity,
-            form.discount_percent,
-            &form.rule_author,
-            &form.rule_category,
-        )
+        insert_new_campaign(&mut conn, form.into_inner())
     })
     .await?
     .map_err(error::ErrorInternalServerError)?;
@@ -0,0 +1,298 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::orders::{self, OrderDto, OrderItem};
+use crate::controllers::payments::PaymentGateway;
+use crate::controllers::quotes;
+use crate::controllers::shipping::DEFAULT_SHIPPING_METHOD;
+use crate::controllers::users::ensure_user_exists;
+use crate::insertables::NewCartItem;
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder, Result};
+use diesel::r2d2::Pool;
+use diesel::upsert::excluded;
+use diesel::{prelude::*, r2d2};
+use r2d2_redis::{redis, RedisConnectionManager};
+use rust_order_api::models::{CartItem, Money};
+use rust_order_api::schema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+#[derive(Deserialize)]
+struct CartItemQuantityDto {
+    quantity: i32,
+}
+
+#[derive(Deserialize)]
+struct CheckoutDto {
+    #[serde(default)]
+    shipping_method: Option<String>,
+    shipping_address_id: i32,
+    #[serde(default)]
+    billing_address_id: Option<i32>,
+    #[serde(default)]
+    payment_method: Option<String>,
+}
+
+fn validate_quantity(_product_id: i32, _quantity: i32) -> Result<(), DbError> {
+    if _quantity < 1 {
+        return Err(ApiError::BadRequest(format!(
+            "Quantity for product {} must be at least 1",
+            _product_id
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+pub fn get_cart_items(conn: &mut PgConnection, _user_id: i32) -> Result<Vec<OrderItem>, DbError> {
+    use schema::cart_items::dsl::*;
+    ensure_user_exists(conn, _user_id)?;
+    let items = cart_items
+        .filter(user_id.eq(_user_id))
+        .select(CartItem::as_select())
+        .order(product_id)
+        .load::<CartItem>(conn)?
+        .into_iter()
+        .map(|item| OrderItem {
+            product_id: item.product_id,
+            quantity: item.quantity,
+        })
+        .collect();
+    Ok(items)
+}
+
+pub fn get_cart_by_user_id(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    _user_id: i32,
+) -> Result<Value, DbError> {
+    let items = get_cart_items(conn, _user_id)?;
+    if items.is_empty() {
+        return Ok(json!({
+            "user_id": _user_id,
+            "subtotal": Money::ZERO,
+            "shipping_method": DEFAULT_SHIPPING_METHOD,
+            "shipping_cost": Money::ZERO,
+            "price_without_discount": Money::ZERO,
+            "discounted_price": Money::ZERO,
+            "campaign_id": null,
+            "best_campaign": null,
+            "campaigns": [],
+            "products": [],
+        }));
+    }
+
+    let mut cart_json = json!({ "user_id": _user_id });
+    if let (Some(cart), Value::Object(quote)) = (
+        cart_json.as_object_mut(),
+        quotes::get_quote(conn, redis_conn, items, None)?,
+    ) {
+        cart.extend(quote);
+    }
+    Ok(cart_json)
+}
+
+/// Adds the item's quantity to the cart, rejecting totals that do not fit a
+/// line.
+pub fn add_cart_item(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    item: &OrderItem,
+) -> Result<(), DbError> {
+    use schema::cart_items::dsl::*;
+    use schema::products::dsl::products;
+
+    ensure_user_exists(conn, _user_id)?;
+    validate_quantity(item.product_id, item.quantity)?;
+    products
+        .find(item.product_id)
+        .select(schema::products::id)
+        .first::<i32>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Product {} not found", item.product_id)))?;
+
+    conn.transaction::<_, DbError, _>(|conn| {
+        let cart_quantity = cart_items
+            .find((_user_id, item.product_id))
+            .select(quantity)
+            .for_update()
+            .first::<i32>(conn)
+            .optional()?
+            .unwrap_or(0);
+        let new_cart_item = NewCartItem {
+            user_id: _user_id,
+            product_id: item.product_id,
+            quantity: cart_quantity.checked_add(item.quantity).ok_or_else(|| {
+                ApiError::BadRequest(format!(
+                    "Quantity for product {} is too large",
+                    item.product_id
+                ))
+            })?,
+        };
+        diesel::insert_into(cart_items)
+            .values(&new_cart_item)
+            .on_conflict((user_id, product_id))
+            .do_update()
+            .set(quantity.eq(excluded(quantity)))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+pub fn update_cart_item(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    _product_id: i32,
+    _quantity: i32,
+) -> Result<(), DbError> {
+    use schema::cart_items::dsl::*;
+    ensure_user_exists(conn, _user_id)?;
+    validate_quantity(_product_id, _quantity)?;
+    let updated = diesel::update(cart_items.find((_user_id, _product_id)))
+        .set(quantity.eq(_quantity))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(
+            ApiError::NotFound(format!("Product {} is not in the cart", _product_id)).into(),
+        );
+    }
+    Ok(())
+}
+
+pub fn remove_cart_item(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    _product_id: i32,
+) -> Result<(), DbError> {
+    use schema::cart_items::dsl::*;
+    ensure_user_exists(conn, _user_id)?;
+    let deleted = diesel::delete(cart_items.find((_user_id, _product_id))).execute(conn)?;
+    if deleted == 0 {
+        return Err(
+            ApiError::NotFound(format!("Product {} is not in the cart", _product_id)).into(),
+        );
+    }
+    Ok(())
+}
+
+pub fn clear_cart_items(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    items: &[OrderItem],
+) -> Result<(), DbError> {
+    use schema::cart_items::dsl::*;
+    let _product_ids: Vec<i32> = items.iter().map(|item| item.product_id).collect();
+    diesel::delete(
+        cart_items
+            .filter(user_id.eq(_user_id))
+            .filter(product_id.eq_any(&_product_ids)),
+    )
+    .execute(conn)?;
+    Ok(())
+}
+
+#[get("/api/users/{user_id}/cart")]
+async fn get_cart(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    user_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let cart = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        get_cart_by_user_id(&mut db_conn, &mut redis_conn, *user_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+#[post("/api/users/{user_id}/cart/items")]
+async fn add_to_cart(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    user_id: web::Path<i32>,
+    form: web::Json<OrderItem>,
+) -> Result<impl Responder> {
+    let cart = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        add_cart_item(&mut db_conn, *user_id, &form)?;
+        get_cart_by_user_id(&mut db_conn, &mut redis_conn, *user_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+#[put("/api/users/{user_id}/cart/items/{product_id}")]
+async fn update_cart(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    path: web::Path<(i32, i32)>,
+    form: web::Json<CartItemQuantityDto>,
+) -> Result<impl Responder> {
+    let (_user_id, _product_id) = path.into_inner();
+    let cart = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        update_cart_item(&mut db_conn, _user_id, _product_id, form.quantity)?;
+        get_cart_by_user_id(&mut db_conn, &mut redis_conn, _user_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+#[delete("/api/users/{user_id}/cart/items/{product_id}")]
+async fn remove_from_cart(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    path: web::Path<(i32, i32)>,
+) -> Result<impl Responder> {
+    let (_user_id, _product_id) = path.into_inner();
+    let cart = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        remove_cart_item(&mut db_conn, _user_id, _product_id)?;
+        get_cart_by_user_id(&mut db_conn, &mut redis_conn, _user_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+#[post("/api/users/{user_id}/cart/checkout")]
+async fn checkout_cart(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    gateway: web::Data<dyn PaymentGateway>,
+    user_id: web::Path<i32>,
+    form: web::Json<CheckoutDto>,
+) -> Result<impl Responder> {
+    let _user_id = user_id.into_inner();
+    let form = form.into_inner();
+    let order = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let items = get_cart_items(&mut db_conn, _user_id)?;
+        let mut redis_conn = redis_pool.get()?;
+        orders::insert_new_order_with(
+            &mut db_conn,
+            &mut redis_conn,
+            &**gateway,
+            OrderDto {
+                user_id: _user_id,
+                items: items.clone(),
+                product_ids: vec![],
+                shipping_method: form.shipping_method,
+                shipping_address_id: form.shipping_address_id,
+                billing_address_id: form.billing_address_id,
+                payment_method: form.payment_method,
+            },
+            |conn, _| clear_cart_items(conn, _user_id, &items),
+        )
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Created().json(order))
+}
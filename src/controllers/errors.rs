@@ -0,0 +1,141 @@
+use actix_web::{error, http::StatusCode, HttpResponse, ResponseError};
+use rust_order_api::models::OrderStatus;
+use serde_json::{json, Value};
+use std::fmt;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    OutOfStock(Vec<i32>),
+    InvalidStatusTransition { from: OrderStatus, to: OrderStatus },
+    IdempotencyKeyReused(String),
+    IdempotencyKeyInProgress(String),
+    PaymentDeclined(String),
+    PaymentGatewayError(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message) => write!(f, "{}", message),
+            ApiError::OutOfStock(product_ids) => {
+                write!(f, "Products out of stock: {:?}", product_ids)
+            }
+            ApiError::InvalidStatusTransition { from, to } => write!(
+                f,
+                "Order status cannot change from {} to {}",
+                from.as_str(),
+                to.as_str()
+            ),
+            ApiError::IdempotencyKeyReused(key) => write!(
+                f,
+                "Idempotency key {} was already used with a different request",
+                key
+            ),
+            ApiError::IdempotencyKeyInProgress(key) => write!(
+                f,
+                "A request with idempotency key {} is still in progress",
+                key
+            ),
+            ApiError::PaymentDeclined(reason) => write!(f, "Payment declined: {}", reason),
+            ApiError::PaymentGatewayError(message) => {
+                write!(f, "Payment gateway error: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ApiError {
+    pub fn to_json(&self) -> Value {
+        match self {
+            ApiError::BadRequest(message) => json!({
+                "error": "bad_request",
+                "message": message,
+            }),
+            ApiError::NotFound(message) => json!({
+                "error": "not_found",
+                "message": message,
+            }),
+            ApiError::Conflict(message) => json!({
+                "error": "conflict",
+                "message": message,
+            }),
+            ApiError::OutOfStock(product_ids) => json!({
+                "error": "out_of_stock",
+                "message": self.to_string(),
+                "product_ids": product_ids,
+            }),
+            ApiError::InvalidStatusTransition { from, to } => json!({
+                "error": "invalid_status_transition",
+                "message": self.to_string(),
+                "from": from,
+                "to": to,
+            }),
+            ApiError::IdempotencyKeyReused(key) => json!({
+                "error": "idempotency_key_reused",
+                "message": self.to_string(),
+                "idempotency_key": key,
+            }),
+            ApiError::IdempotencyKeyInProgress(key) => json!({
+                "error": "idempotency_key_in_progress",
+                "message": self.to_string(),
+                "idempotency_key": key,
+            }),
+            ApiError::PaymentDeclined(reason) => json!({
+                "error": "payment_declined",
+                "message": self.to_string(),
+                "reason": reason,
+            }),
+            ApiError::PaymentGatewayError(_) => json!({
+                "error": "payment_gateway_error",
+                "message": self.to_string(),
+            }),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_)
+            | ApiError::OutOfStock(_)
+            | ApiError::InvalidStatusTransition { .. }
+            | ApiError::IdempotencyKeyInProgress(_) => StatusCode::CONFLICT,
+            ApiError::IdempotencyKeyReused(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::PaymentDeclined(_) => StatusCode::PAYMENT_REQUIRED,
+            ApiError::PaymentGatewayError(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.to_json())
+    }
+}
+
+pub fn into_response_error(err: DbError) -> actix_web::Error {
+    match err.downcast::<ApiError>() {
+        Ok(api_error) => (*api_error).into(),
+        Err(err) => error::ErrorInternalServerError(err),
+    }
+}
+
+/// The error as the JSON body an API response would carry, for errors that
+/// are stored rather than returned.
+pub fn get_error_json(err: &DbError) -> Value {
+    match err.downcast_ref::<ApiError>() {
+        Some(api_error) => api_error.to_json(),
+        None => json!({
+            "error": "internal_error",
+            "message": err.to_string(),
+        }),
+    }
+}
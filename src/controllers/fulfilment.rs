@@ -0,0 +1,154 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::notifications;
+use crate::controllers::orders;
+use crate::controllers::payments::PaymentGateway;
+use crate::controllers::users::ensure_user_exists;
+use crate::QueryOrder;
+use actix_web::{get, web, HttpResponse, Responder, Result};
+use diesel::upsert::excluded;
+use diesel::{prelude::*, r2d2};
+use rust_order_api::models::{Money, Order, OrderStatus, OrderToProduct, UserOrderStats};
+use rust_order_api::schema;
+use serde_json::{json, Value};
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+fn update_user_stats(conn: &mut PgConnection, order: &Order) -> Result<(), DbError> {
+    use schema::user_order_stats::dsl::*;
+    diesel::insert_into(user_order_stats)
+        .values((
+            user_id.eq(order.user_id),
+            order_count.eq(1),
+            total_spent.eq(order.discounted_price),
+            last_order_at.eq(order.created_at),
+        ))
+        .on_conflict(user_id)
+        .do_update()
+        .set((
+            order_count.eq(order_count + 1),
+            total_spent.eq(total_spent + excluded(total_spent)),
+            last_order_at.eq(excluded(last_order_at)),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Takes a cancelled order back out of its user's statistics.
+pub fn remove_user_stats(conn: &mut PgConnection, order: &Order) -> Result<(), DbError> {
+    use schema::user_order_stats::dsl::*;
+    diesel::update(user_order_stats)
+        .filter(user_id.eq(order.user_id))
+        .set((
+            order_count.eq(order_count - 1),
+            total_spent.eq(total_spent - order.discounted_price),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Adds the price difference of an edited order to its user's total spent.
+pub fn update_user_total_spent(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    difference: Money,
+) -> Result<(), DbError> {
+    use schema::user_order_stats::dsl::*;
+    diesel::update(user_order_stats)
+        .filter(user_id.eq(_user_id))
+        .set(total_spent.eq(total_spent + difference))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Post-order processing run by the `order_service` worker. The order was
+/// created as `processing` with its stock reserved and its lines written;
+/// this updates the user's statistics, records the confirmation and moves the
+/// order to `pending`, or to `review` when it was flagged by the risk checks,
+/// all in one transaction.
+/// Orders that already left `processing` are skipped, so a job that is
+/// delivered twice does not count the order twice.
+pub fn fulfil_order(conn: &mut PgConnection, job: &QueryOrder) -> Result<(), DbError> {
+    conn.transaction::<_, DbError, _>(|conn| {
+        let order = schema::orders::table
+            .filter(schema::orders::id.eq(job.id))
+            .for_update()
+            .first::<Order>(conn)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", job.id)))?;
+        if order.status != OrderStatus::Processing {
+            return Ok(());
+        }
+
+        let order_lines = OrderToProduct::belonging_to(&order)
+            .select(OrderToProduct::as_select())
+            .order(schema::orders_products::product_id)
+            .load::<OrderToProduct>(conn)?;
+        update_user_stats(conn, &order)?;
+        let next_status = match order.risk_flags {
+            Some(_) => OrderStatus::Review,
+            None => OrderStatus::Pending,
+        };
+        orders::transition_order_status(conn, order.id, next_status)?;
+        notifications::send_order_confirmation(conn, &order, &order_lines)?;
+        Ok(())
+    })
+}
+
+/// Cancels an order whose job is discarded before the worker could fulfil
+/// it, giving back its stock and payment. Orders that already left
+/// `processing` are left alone.
+pub fn release_order(
+    conn: &mut PgConnection,
+    gateway: &dyn PaymentGateway,
+    job: &QueryOrder,
+    reason: &str,
+) -> Result<(), DbError> {
+    let order = schema::orders::table
+        .filter(schema::orders::id.eq(job.id))
+        .for_update()
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", job.id)))?;
+    if order.status != OrderStatus::Processing {
+        return Ok(());
+    }
+
+    orders::cancel_order_by_id(
+        conn,
+        gateway,
+        order.id,
+        Some("system".to_string()),
+        Some(reason.to_string()),
+    )?;
+    Ok(())
+}
+
+pub fn get_user_stats(conn: &mut PgConnection, _user_id: i32) -> Result<Value, DbError> {
+    use schema::user_order_stats::dsl::*;
+    ensure_user_exists(conn, _user_id)?;
+    let stats = user_order_stats
+        .filter(user_id.eq(_user_id))
+        .select(UserOrderStats::as_select())
+        .first::<UserOrderStats>(conn)
+        .optional()?;
+    Ok(match stats {
+        Some(stats) => json!(stats),
+        None => json!({
+            "user_id": _user_id,
+            "order_count": 0,
+            "total_spent": Money::ZERO,
+            "last_order_at": null,
+        }),
+    })
+}
+
+#[get("/api/users/{user_id}/stats")]
+async fn get_stats(pool: web::Data<DbPool>, user_id: web::Path<i32>) -> Result<impl Responder> {
+    let stats = web::block(move || {
+        let mut conn = pool.get()?;
+        get_user_stats(&mut conn, *user_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(stats))
+}
@@ -1,10 +1,71 @@
+use crate::controllers::errors::ApiError;
 use crate::orders;
-use rust_order_api::models::Campaign;
+use rust_decimal::RoundingStrategy;
+use rust_order_api::models::{Campaign, Money, Order, ShippingMethod};
+use serde_json::{json, Value};
+
+pub struct CampaignPrice {
+    pub campaign: Campaign,
+    pub discounted_price: Money,
+}
+
+pub struct OrderPricing {
+    pub subtotal: Money,
+    pub shipping_method: ShippingMethod,
+    pub shipping_cost: Money,
+    pub price_without_discount: Money,
+    pub campaign_prices: Vec<CampaignPrice>,
+    pub campaign_id: Option<i32>,
+    pub discounted_price: Money,
+}
+
+impl OrderPricing {
+    pub fn discount_amount(&self) -> Money {
+        self.price_without_discount - self.discounted_price
+    }
+
+    pub fn pricing_rules(&self) -> Value {
+        let applied_campaign = self
+            .campaign_prices
+            .iter()
+            .map(|campaign_price| &campaign_price.campaign)
+            .find(|campaign| Some(campaign.id) == self.campaign_id);
+
+        json!({
+            "shipping_method": {
+                "code": self.shipping_method.code,
+                "name": self.shipping_method.name,
+                "base_fee": self.shipping_method.base_fee,
+                "per_item_fee": self.shipping_method.per_item_fee,
+                "per_kg_fee": self.shipping_method.per_kg_fee,
+                "free_shipping_threshold": self.shipping_method.free_shipping_threshold,
+            },
+            "campaign": applied_campaign.map(|campaign| {
+                json!({
+                    "id": campaign.id,
+                    "description": campaign.description,
+                    "min_purchase_price": campaign.min_purchase_price,
+                    "min_purchase_quantity": campaign.min_purchase_quantity,
+                    "discount_quantity": campaign.discount_quantity,
+                    "discount_percent": campaign.discount_percent,
+                    "rule_author": campaign.rule_author,
+                    "rule_category": campaign.rule_category,
+                })
+            }),
+        })
+    }
+}
+
+fn add_quantity(total: i32, product: &orders::ProductWithCategory) -> Result<i32, ApiError> {
+    total
+        .checked_add(product.quantity)
+        .ok_or_else(|| ApiError::BadRequest("Order quantity is too large".to_string()))
+}
 
 pub fn get_available_campaigns(
     _campaigns: Vec<Campaign>,
-    products: &mut Vec<orders::ProductWithCategory>,
-) -> Vec<Campaign> {
+    products: &[orders::ProductWithCategory],
+) -> Result<Vec<Campaign>, ApiError> {
     let mut available_campaigns = Vec::new();
     for campaign in _campaigns {
         let conditions = (campaign.rule_author.is_some()
@@ -17,7 +78,7 @@ pub fn get_available_campaigns(
                     &product.product.author == campaign.rule_author.as_ref().unwrap()
                         && &product.category_title == campaign.rule_category.as_ref().unwrap()
                 })
-                .count() as i32
+                .try_fold(0, add_quantity)?
                 >= campaign.min_purchase_quantity.unwrap())
             || (campaign.rule_author.is_some()
                 && campaign.rule_category.is_some()
@@ -29,7 +90,7 @@ pub fn get_available_campaigns(
                         &product.product.author == campaign.rule_author.as_ref().unwrap()
                             && &product.category_title == campaign.rule_category.as_ref().unwrap()
                     })
-                    .count() as i32
+                    .try_fold(0, add_quantity)?
                     >= campaign.min_purchase_quantity.unwrap())
             || (campaign.rule_category.is_some()
                 && campaign.rule_author.is_none()
@@ -40,7 +101,7 @@ pub fn get_available_campaigns(
                     .filter(|product| {
                         &product.category_title == campaign.rule_category.as_ref().unwrap()
                     })
-                    .count() as i32
+                    .try_fold(0, add_quantity)?
                     >= campaign.min_purchase_quantity.unwrap())
             || (campaign.rule_author.is_some()
                 && campaign.rule_category.is_none()
@@ -51,7 +112,7 @@ pub fn get_available_campaigns(
                     .filter(|product| {
                         &product.product.author == campaign.rule_author.as_ref().unwrap()
                     })
-                    .count() as i32
+                    .try_fold(0, add_quantity)?
                     >= campaign.min_purchase_quantity.unwrap())
             || (campaign.rule_category.is_some()
                 && campaign.rule_author.is_none()
@@ -62,7 +123,7 @@ pub fn get_available_campaigns(
                     .filter(|product| {
                         &product.category_title == campaign.rule_category.as_ref().unwrap()
                     })
-                    .count() as i32
+                    .try_fold(0, add_quantity)?
                     >= campaign.min_purchase_quantity.unwrap())
             || (campaign.rule_author.is_some()
                 && campaign.rule_category.is_none()
@@ -73,23 +134,25 @@ pub fn get_available_campaigns(
                     .filter(|product| {
                         &product.product.author == campaign.rule_author.as_ref().unwrap()
                     })
-                    .count() as i32
+                    .try_fold(0, add_quantity)?
                     >= campaign.min_purchase_quantity.unwrap())
             || (campaign.min_purchase_quantity.is_some()
                 && campaign.discount_quantity.is_some()
                 && campaign.rule_category.is_none()
                 && campaign.rule_author.is_none()
-                && products.len() as i32 >= campaign.min_purchase_quantity.unwrap())
+                && products.iter().try_fold(0, add_quantity)?
+                    >= campaign.min_purchase_quantity.unwrap())
             || (campaign.min_purchase_quantity.is_some()
                 && campaign.discount_percent.is_some()
                 && campaign.rule_category.is_none()
                 && campaign.rule_author.is_none()
-                && products.len() as i32 >= campaign.min_purchase_quantity.unwrap())
+                && products.iter().try_fold(0, add_quantity)?
+                    >= campaign.min_purchase_quantity.unwrap())
             || (campaign.min_purchase_price.is_some()
                 && products
                     .iter()
-                    .map(|product| product.product.list_price)
-                    .sum::<f64>()
+                    .map(|product| product.product.list_price * Money::from(product.quantity))
+                    .sum::<Money>()
                     >= campaign.min_purchase_price.unwrap());
 
         if conditions {
@@ -103,6 +166,8 @@ pub fn get_available_campaigns(
                     discount_percent: campaign.discount_percent,
                     rule_author: campaign.rule_author,
                     rule_category: campaign.rule_category,
+                    created_at: campaign.created_at,
+                    updated_at: campaign.updated_at,
                 });
             } else if let Some(_discount_quantity_value) = campaign.discount_quantity {
                 available_campaigns.push(Campaign {
@@ -114,23 +179,24 @@ pub fn get_available_campaigns(
                     discount_percent: campaign.discount_percent,
                     rule_author: campaign.rule_author,
                     rule_category: campaign.rule_category,
+                    created_at: campaign.created_at,
+                    updated_at: campaign.updated_at,
                 });
             }
         }
     }
-    available_campaigns
+    Ok(available_campaigns)
 }
 
 pub fn get_discounted_total_price(
     campaign: &Campaign,
-    products: &mut Vec<orders::ProductWithCategory>,
-    total_price: f64,
-) -> f64 {
+    products: &[orders::ProductWithCategory],
+    total_price: Money,
+) -> Money {
     if let Some(discount_percent_value) = campaign.discount_percent {
-        let discounted_price = total_price - (total_price * discount_percent_value as f64) / 100.0;
-        discounted_price
+        total_price - get_percent_of(total_price, discount_percent_value)
     } else if let Some(discount_quantity_value) = campaign.discount_quantity {
-        let mut eligible_products = products
+        let mut eligible_prices = products
             .iter()
             .filter(|product| {
                 (campaign.rule_author.is_none()
@@ -138,24 +204,373 @@ pub fn get_discounted_total_price(
                     && (campaign.rule_category.is_none()
                         || campaign.rule_category.as_ref() == Some(&product.category_title))
             })
+            .map(|product| (product.product.list_price, product.quantity.max(0)))
             .collect::<Vec<_>>();
 
-        eligible_products.sort_by(|a, b| {
-            a.product
-                .list_price
-                .partial_cmp(&b.product.list_price)
-                .unwrap()
-        });
-        eligible_products.truncate(discount_quantity_value as usize);
+        eligible_prices.sort();
 
-        let discounted_price = total_price
-            - eligible_products
-                .iter()
-                .map(|product| product.product.list_price)
-                .sum::<f64>();
+        // Take the cheapest units a line at a time instead of one entry per unit.
+        let mut free_units = discount_quantity_value.max(0);
+        let mut free_price = Money::ZERO;
+        for (list_price, quantity) in eligible_prices {
+            if free_units == 0 {
+                break;
+            }
+            let units = quantity.min(free_units);
+            free_price += list_price * Money::from(units);
+            free_units -= units;
+        }
 
-        discounted_price
+        total_price - free_price
     } else {
-        0.0
+        Money::ZERO
+    }
+}
+
+pub fn get_shipping_cost(
+    shipping_method: &ShippingMethod,
+    products: &[orders::ProductWithCategory],
+    subtotal: Money,
+) -> Result<Money, ApiError> {
+    let too_large = || ApiError::BadRequest("Order is too large to ship".to_string());
+    let mut units: i32 = 0;
+    let mut weight_grams: i32 = 0;
+    for product in products {
+        units = units.checked_add(product.quantity).ok_or_else(too_large)?;
+        weight_grams = product
+            .product
+            .weight_grams
+            .checked_mul(product.quantity)
+            .and_then(|line_weight| weight_grams.checked_add(line_weight))
+            .ok_or_else(too_large)?;
+    }
+
+    if let Some(free_shipping_threshold) = shipping_method.free_shipping_threshold {
+        if subtotal >= free_shipping_threshold {
+            return Ok(Money::ZERO);
+        }
+    }
+    Ok(round_money(
+        shipping_method.base_fee
+            + shipping_method.per_item_fee * Money::from(units)
+            + shipping_method.per_kg_fee * Money::from(weight_grams) / Money::ONE_THOUSAND,
+    ))
+}
+
+pub fn get_order_pricing(
+    _campaigns: Vec<Campaign>,
+    products: &[orders::ProductWithCategory],
+    shipping_method: ShippingMethod,
+) -> Result<OrderPricing, ApiError> {
+    let subtotal: Money = products
+        .iter()
+        .map(|product| product.product.list_price * Money::from(product.quantity))
+        .sum();
+    let shipping_cost = get_shipping_cost(&shipping_method, products, subtotal)?;
+    let total_price = subtotal + shipping_cost;
+
+    let campaign_prices: Vec<CampaignPrice> = get_available_campaigns(_campaigns, products)?
+        .into_iter()
+        .map(|campaign| {
+            let discounted_price = get_discounted_total_price(&campaign, products, total_price);
+            CampaignPrice {
+                campaign,
+                discounted_price,
+            }
+        })
+        .collect();
+
+    let (campaign_id, discounted_price) = match campaign_prices
+        .iter()
+        .min_by_key(|campaign_price| campaign_price.discounted_price)
+    {
+        Some(best_price) => (Some(best_price.campaign.id), best_price.discounted_price),
+        None => (None, total_price),
+    };
+
+    Ok(OrderPricing {
+        subtotal,
+        shipping_method,
+        shipping_cost,
+        price_without_discount: total_price,
+        campaign_prices,
+        campaign_id,
+        discounted_price,
+    })
+}
+
+/// Rounds an amount to whole cents, with halves rounded away from zero.
+pub fn round_money(amount: Money) -> Money {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Percent discounts are computed on the exact amount and then rounded to
+/// whole cents, so a 10% discount on 48.75 is 4.88 and not 4.875.
+pub fn get_percent_of(amount: Money, percent: i32) -> Money {
+    round_money(amount * Money::from(percent) / Money::ONE_HUNDRED)
+}
+
+/// Refund owed for returned items worth `returned_value` at their purchase
+/// price. The campaign discount was taken from the whole order total, so the
+/// returned items give back their proportional share of it. Shipping is not refunded.
+pub fn get_refund_amount(order: &Order, returned_value: Money) -> Money {
+    if order.price_without_discount.is_zero() {
+        return round_money(returned_value);
+    }
+    round_money(
+        returned_value - order.discount_amount * returned_value / order.price_without_discount,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use rust_order_api::models::{OrderStatus, Product};
+
+    fn money(amount: &str) -> Money {
+        amount.parse().unwrap()
+    }
+
+    fn product(
+        id: i32,
+        author: &str,
+        category: &str,
+        list_price: &str,
+        quantity: i32,
+    ) -> orders::ProductWithCategory {
+        orders::ProductWithCategory {
+            product: Product {
+                id,
+                title: format!("Book {}", id),
+                category_id: 1,
+                author: author.to_string(),
+                list_price: money(list_price),
+                stock_quantity: 100,
+                created_at: NaiveDateTime::default(),
+                updated_at: NaiveDateTime::default(),
+                weight_grams: 500,
+            },
+            category_title: category.to_string(),
+            quantity,
+        }
+    }
+
+    fn campaign(discount_percent: Option<i32>, discount_quantity: Option<i32>) -> Campaign {
+        Campaign {
+            id: 1,
+            description: "Test campaign".to_string(),
+            min_purchase_price: None,
+            min_purchase_quantity: None,
+            discount_quantity,
+            discount_percent,
+            rule_author: None,
+            rule_category: None,
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn shipping_method() -> ShippingMethod {
+        ShippingMethod {
+            id: 1,
+            code: "standard".to_string(),
+            name: "Standard shipping".to_string(),
+            base_fee: money("10"),
+            per_item_fee: money("1"),
+            per_kg_fee: money("2"),
+            free_shipping_threshold: Some(money("150")),
+            is_active: true,
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn order(price_without_discount: &str, discount_amount: &str) -> Order {
+        let price_without_discount = money(price_without_discount);
+        let discount_amount = money(discount_amount);
+        Order {
+            id: 1,
+            price_without_discount,
+            discounted_price: price_without_discount - discount_amount,
+            campaign_id: None,
+            user_id: 1,
+            status: OrderStatus::Delivered,
+            cancelled_by: None,
+            cancellation_reason: None,
+            cancelled_at: None,
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+            subtotal: price_without_discount,
+            shipping_fee: Money::ZERO,
+            discount_amount,
+            pricing_rules: json!({}),
+            shipping_method: "standard".to_string(),
+            shipping_address: None,
+            billing_address: None,
+            risk_flags: None,
+        }
+    }
+
+    #[test]
+    fn money_is_rounded_to_cents_with_halves_away_from_zero() {
+        assert_eq!(round_money(money("4.875")), money("4.88"));
+        assert_eq!(round_money(money("-4.875")), money("-4.88"));
+        assert_eq!(round_money(money("4.8749")), money("4.87"));
+        assert_eq!(round_money(money("12")), money("12"));
+    }
+
+    #[test]
+    fn percent_is_taken_from_the_exact_amount() {
+        assert_eq!(get_percent_of(money("48.75"), 10), money("4.88"));
+        assert_eq!(get_percent_of(money("0.10"), 5), money("0.01"));
+        assert_eq!(get_percent_of(money("200"), 0), money("0"));
+    }
+
+    #[test]
+    fn percent_discount_is_taken_from_the_total() {
+        let products = [product(1, "Sabahattin Ali", "Roman", "48.75", 1)];
+        assert_eq!(
+            get_discounted_total_price(&campaign(Some(10), None), &products, money("48.75")),
+            money("43.87")
+        );
+    }
+
+    #[test]
+    fn quantity_discount_frees_the_cheapest_eligible_units() {
+        let mut rule = campaign(None, Some(3));
+        rule.rule_author = Some("Sabahattin Ali".to_string());
+        let products = [
+            product(1, "Sabahattin Ali", "Roman", "20", 2),
+            product(2, "Sabahattin Ali", "Roman", "12.50", 2),
+            product(3, "Oğuz Atay", "Roman", "5", 4),
+        ];
+        // Both 12.50 units and one 20 unit are free; the cheaper books by
+        // another author are not eligible.
+        assert_eq!(
+            get_discounted_total_price(&rule, &products, money("100")),
+            money("55")
+        );
+    }
+
+    #[test]
+    fn quantity_discount_is_capped_at_the_eligible_units() {
+        let mut rule = campaign(None, Some(5));
+        rule.rule_category = Some("Roman".to_string());
+        let products = [
+            product(1, "Sabahattin Ali", "Roman", "20", 1),
+            product(2, "Sabahattin Ali", "Şiir", "10", 1),
+        ];
+        assert_eq!(
+            get_discounted_total_price(&rule, &products, money("30")),
+            money("10")
+        );
+    }
+
+    #[test]
+    fn quantity_discount_handles_large_quantities() {
+        let products = [product(1, "Sabahattin Ali", "Roman", "1", i32::MAX)];
+        assert_eq!(
+            get_discounted_total_price(&campaign(None, Some(2)), &products, money("2147483647")),
+            money("2147483645")
+        );
+    }
+
+    #[test]
+    fn shipping_cost_adds_item_and_weight_fees_to_the_base_fee() {
+        let products = [
+            product(1, "Sabahattin Ali", "Roman", "20", 2),
+            product(2, "Oğuz Atay", "Roman", "15.25", 1),
+        ];
+        // 10 base + 3 items + 1.5 kg at 2 per kg.
+        assert_eq!(
+            get_shipping_cost(&shipping_method(), &products, money("55.25")).unwrap(),
+            money("16")
+        );
+    }
+
+    #[test]
+    fn shipping_is_free_from_the_threshold() {
+        let products = [product(1, "Sabahattin Ali", "Roman", "75", 2)];
+        assert_eq!(
+            get_shipping_cost(&shipping_method(), &products, money("150")).unwrap(),
+            Money::ZERO
+        );
+
+        let mut no_threshold = shipping_method();
+        no_threshold.free_shipping_threshold = None;
+        assert_eq!(
+            get_shipping_cost(&no_threshold, &products, money("150")).unwrap(),
+            money("14")
+        );
+    }
+
+    #[test]
+    fn refund_gives_back_the_returned_share_of_the_discount() {
+        assert_eq!(
+            get_refund_amount(&order("200", "20"), money("50")),
+            money("45")
+        );
+        assert_eq!(
+            get_refund_amount(&order("300", "10"), money("100")),
+            money("96.67")
+        );
+    }
+
+    #[test]
+    fn refund_of_the_whole_order_is_its_discounted_price() {
+        let whole_order = order("123.45", "12.35");
+        assert_eq!(
+            get_refund_amount(&whole_order, whole_order.price_without_discount),
+            whole_order.discounted_price
+        );
+    }
+
+    #[test]
+    fn refund_without_discount_is_the_returned_value() {
+        assert_eq!(
+            get_refund_amount(&order("80", "0"), money("30.005")),
+            money("30.01")
+        );
+        assert_eq!(
+            get_refund_amount(&order("0", "0"), money("30")),
+            money("30")
+        );
+    }
+
+    #[test]
+    fn shipping_cost_rejects_orders_too_large_to_ship() {
+        let products = [
+            product(1, "Sabahattin Ali", "Roman", "1", i32::MAX),
+            product(2, "Sabahattin Ali", "Roman", "1", 1),
+        ];
+        assert!(matches!(
+            get_shipping_cost(&shipping_method(), &products, money("1000")),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn campaigns_reject_quantities_that_overflow() {
+        let products = [
+            product(1, "Sabahattin Ali", "Roman", "1", i32::MAX),
+            product(2, "Sabahattin Ali", "Roman", "1", 1),
+        ];
+        let mut quantity_campaign = campaign(Some(10), None);
+        quantity_campaign.min_purchase_quantity = Some(3);
+        assert!(matches!(
+            get_available_campaigns(vec![quantity_campaign], &products),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        let products = [product(1, "Sabahattin Ali", "Roman", "1", 3)];
+        let mut quantity_campaign = campaign(Some(10), None);
+        quantity_campaign.min_purchase_quantity = Some(3);
+        assert_eq!(
+            get_available_campaigns(vec![quantity_campaign], &products)
+                .unwrap()
+                .len(),
+            1
+        );
     }
 }
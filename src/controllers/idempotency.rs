@@ -0,0 +1,282 @@
+use crate::controllers::errors::ApiError;
+use actix_web::{web, HttpRequest};
+use diesel::dsl::{now, IntervalDsl};
+use diesel::prelude::*;
+use diesel::r2d2::Pool;
+use r2d2_redis::{redis, RedisConnectionManager};
+use rust_order_api::schema::idempotency_keys::dsl::*;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// How long the response of a finished request is replayed.
+const IDEMPOTENCY_KEY_TTL_HOURS: i32 = 24;
+/// How long a key stays reserved for a request that has not finished, so a
+/// request that dies midway does not block its key for a whole day. Running
+/// requests renew the reservation every `IDEMPOTENCY_LOCK_RENEW_INTERVAL`.
+const IDEMPOTENCY_LOCK_TTL: usize = 60;
+const IDEMPOTENCY_LOCK_RENEW_INTERVAL: Duration = Duration::from_secs(20);
+
+pub enum IdempotencyState {
+    New,
+    Completed(Value),
+}
+
+pub fn get_idempotency_key(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+pub fn get_fingerprint<T: Serialize>(payload: &T) -> Result<String, DbError> {
+    let body = serde_json::to_vec(payload)?;
+    Ok(hex::encode(Sha256::digest(body)))
+}
+
+/// Keys are scoped to the user, so two users sending the same key never see
+/// each other's orders.
+fn redis_key(_user_id: i32, key: &str) -> String {
+    format!("idempotency:{}:{}", _user_id, key)
+}
+
+/// The fingerprint and response saved for a finished request, if it has not
+/// expired. Expired responses of the user are deleted first, so the key can be
+/// used again.
+fn get_completed_request(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    key: &str,
+) -> Result<Option<(String, Value)>, DbError> {
+    diesel::delete(idempotency_keys)
+        .filter(user_id.eq(_user_id))
+        .filter(created_at.lt(now - IDEMPOTENCY_KEY_TTL_HOURS.hours()))
+        .execute(conn)?;
+    let completed = idempotency_keys
+        .find((_user_id, key))
+        .select((fingerprint, response))
+        .first::<(String, Value)>(conn)
+        .optional()?;
+    Ok(completed)
+}
+
+/// Decides what to do with a request whose key is already taken, given the
+/// request that finished with it, or else the fingerprint of the one still
+/// holding it. A reservation that expired between the two lookups is treated
+/// as still in progress, so the client retries instead of racing another
+/// request.
+fn get_stored_state(
+    key: &str,
+    request_fingerprint: &str,
+    completed: Option<(String, Value)>,
+    reserved_fingerprint: Option<String>,
+) -> Result<IdempotencyState, ApiError> {
+    match (completed, reserved_fingerprint) {
+        (Some((stored_fingerprint, _)), _) | (None, Some(stored_fingerprint))
+            if stored_fingerprint != request_fingerprint =>
+        {
+            Err(ApiError::IdempotencyKeyReused(key.to_owned()))
+        }
+        (Some((_, stored_response)), _) => Ok(IdempotencyState::Completed(stored_response)),
+        (None, _) => Err(ApiError::IdempotencyKeyInProgress(key.to_owned())),
+    }
+}
+
+/// Replays the response of a finished request, or reserves the key in Redis
+/// for a new one. Responses are kept in Postgres, saved by `complete_request`
+/// with the work of the request.
+pub fn begin_request(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    _user_id: i32,
+    key: &str,
+    request_fingerprint: &str,
+) -> Result<IdempotencyState, DbError> {
+    if let Some(completed) = get_completed_request(conn, _user_id, key)? {
+        return Ok(get_stored_state(
+            key,
+            request_fingerprint,
+            Some(completed),
+            None,
+        )?);
+    }
+
+    let reserved: Option<String> = redis::cmd("SET")
+        .arg(redis_key(_user_id, key))
+        .arg(request_fingerprint)
+        .arg("NX")
+        .arg("EX")
+        .arg(IDEMPOTENCY_LOCK_TTL)
+        .query(redis_conn)?;
+    if reserved.is_some() {
+        return Ok(IdempotencyState::New);
+    }
+
+    // Another request holds the key, or finished since the first lookup.
+    let reserved_fingerprint: Option<String> = redis::cmd("GET")
+        .arg(redis_key(_user_id, key))
+        .query(redis_conn)?;
+    let completed = get_completed_request(conn, _user_id, key)?;
+    Ok(get_stored_state(
+        key,
+        request_fingerprint,
+        completed,
+        reserved_fingerprint,
+    )?)
+}
+
+/// Keeps the key reserved while its request runs. The task running this is
+/// aborted once the request is done.
+pub async fn keep_reserved(redis_pool: Pool<RedisConnectionManager>, _user_id: i32, key: String) {
+    loop {
+        actix_web::rt::time::sleep(IDEMPOTENCY_LOCK_RENEW_INTERVAL).await;
+        let redis_pool = redis_pool.clone();
+        let lock_key = redis_key(_user_id, &key);
+        let renewed = web::block(move || -> Result<(), DbError> {
+            let mut redis_conn = redis_pool.get()?;
+            redis::cmd("EXPIRE")
+                .arg(lock_key)
+                .arg(IDEMPOTENCY_LOCK_TTL)
+                .query::<()>(&mut *redis_conn)?;
+            Ok(())
+        })
+        .await;
+        match renewed {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::warn!("Could not renew idempotency key {}: {}", key, err),
+            Err(err) => tracing::warn!("Could not renew idempotency key {}: {}", key, err),
+        }
+    }
+}
+
+/// Saves the response of a request in the transaction that did its work, so
+/// the two commit together. Should another request with the key have
+/// finished first, this one fails and its work is rolled back.
+pub fn complete_request(
+    conn: &mut PgConnection,
+    _user_id: i32,
+    key: &str,
+    request_fingerprint: &str,
+    request_response: &Value,
+) -> Result<(), DbError> {
+    let inserted = diesel::insert_into(idempotency_keys)
+        .values((
+            user_id.eq(_user_id),
+            idempotency_key.eq(key),
+            fingerprint.eq(request_fingerprint),
+            response.eq(request_response),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    if inserted == 0 {
+        return Err(ApiError::IdempotencyKeyInProgress(key.to_owned()).into());
+    }
+    Ok(())
+}
+
+/// Frees the key once its request is done, whether it succeeded or not.
+pub fn release_request(
+    redis_conn: &mut redis::Connection,
+    _user_id: i32,
+    key: &str,
+) -> Result<(), DbError> {
+    let _: () = redis::cmd("DEL")
+        .arg(redis_key(_user_id, key))
+        .query(redis_conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    fn completed(stored_fingerprint: &str, stored_response: Value) -> Option<(String, Value)> {
+        Some((stored_fingerprint.to_owned(), stored_response))
+    }
+
+    #[test]
+    fn idempotency_key_is_trimmed_and_must_not_be_empty() {
+        let req = TestRequest::default()
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "  order-1 "))
+            .to_http_request();
+        assert_eq!(get_idempotency_key(&req).as_deref(), Some("order-1"));
+
+        let req = TestRequest::default()
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "   "))
+            .to_http_request();
+        assert_eq!(get_idempotency_key(&req), None);
+        assert_eq!(
+            get_idempotency_key(&TestRequest::default().to_http_request()),
+            None
+        );
+    }
+
+    #[test]
+    fn fingerprint_depends_only_on_the_payload() {
+        let payload = json!({ "user_id": 1, "items": [{ "product_id": 2, "quantity": 1 }] });
+        let payload_fingerprint = get_fingerprint(&payload).unwrap();
+        assert_eq!(
+            payload_fingerprint,
+            get_fingerprint(&payload.clone()).unwrap()
+        );
+        assert_eq!(payload_fingerprint.len(), 64);
+
+        let other_payload = json!({ "user_id": 1, "items": [{ "product_id": 2, "quantity": 2 }] });
+        assert_ne!(
+            payload_fingerprint,
+            get_fingerprint(&other_payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn redis_keys_are_scoped_to_the_user() {
+        assert_ne!(redis_key(1, "order-1"), redis_key(2, "order-1"));
+    }
+
+    #[test]
+    fn completed_request_with_the_same_payload_is_replayed() {
+        let stored_response = json!({ "id": 7 });
+        let state = get_stored_state(
+            "order-1",
+            "abc",
+            completed("abc", stored_response.clone()),
+            None,
+        );
+        assert!(
+            matches!(state, Ok(IdempotencyState::Completed(replayed)) if replayed == stored_response)
+        );
+    }
+
+    #[test]
+    fn key_reused_with_another_payload_is_rejected() {
+        let state = get_stored_state("order-1", "abc", completed("def", json!({ "id": 7 })), None);
+        assert!(matches!(state, Err(ApiError::IdempotencyKeyReused(_))));
+        let state = get_stored_state("order-1", "abc", None, Some("def".to_owned()));
+        assert!(matches!(state, Err(ApiError::IdempotencyKeyReused(_))));
+    }
+
+    #[test]
+    fn completed_request_wins_over_a_new_reservation() {
+        let state = get_stored_state(
+            "order-1",
+            "abc",
+            completed("abc", json!({ "id": 7 })),
+            Some("abc".to_owned()),
+        );
+        assert!(matches!(state, Ok(IdempotencyState::Completed(_))));
+    }
+
+    #[test]
+    fn reserved_or_expired_request_is_in_progress() {
+        let state = get_stored_state("order-1", "abc", None, Some("abc".to_owned()));
+        assert!(matches!(state, Err(ApiError::IdempotencyKeyInProgress(_))));
+        let state = get_stored_state("order-1", "abc", None, None);
+        assert!(matches!(state, Err(ApiError::IdempotencyKeyInProgress(_))));
+    }
+}
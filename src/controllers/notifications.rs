@@ -0,0 +1,74 @@
+use crate::controllers::errors;
+use crate::controllers::users::ensure_user_exists;
+use crate::insertables::NewNotification;
+use actix_web::{get, web, HttpResponse, Responder, Result};
+use diesel::{prelude::*, r2d2};
+use rust_order_api::models::{Notification, Order, OrderToProduct};
+use rust_order_api::schema;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+pub const ORDER_CONFIRMATION: &str = "order_confirmation";
+
+/// Records the confirmation for a processed order. Notifications are stored
+/// per user and can be read back through the API; delivering them by email or
+/// push is left to whatever consumes this table.
+pub fn send_order_confirmation(
+    conn: &mut PgConnection,
+    order: &Order,
+    order_lines: &[OrderToProduct],
+) -> Result<Notification, DbError> {
+    let line_summary = order_lines
+        .iter()
+        .map(|line| {
+            format!(
+                "{} x {} ({:.2})",
+                line.quantity, line.title, line.unit_price
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let new_notification = NewNotification {
+        user_id: order.user_id,
+        order_id: Some(order.id),
+        kind: ORDER_CONFIRMATION.to_string(),
+        subject: format!("Your order #{} has been received", order.id),
+        body: format!(
+            "{}\nShipping: {:.2}\nTotal: {:.2}",
+            line_summary, order.shipping_fee, order.discounted_price
+        ),
+    };
+    let notification = diesel::insert_into(schema::notifications::table)
+        .values(&new_notification)
+        .returning(Notification::as_returning())
+        .get_result(conn)?;
+    Ok(notification)
+}
+
+pub fn get_user_notifications(
+    conn: &mut PgConnection,
+    _user_id: i32,
+) -> Result<Vec<Notification>, DbError> {
+    use schema::notifications::dsl::*;
+    ensure_user_exists(conn, _user_id)?;
+    let user_notifications = notifications
+        .filter(user_id.eq(_user_id))
+        .select(Notification::as_select())
+        .order(id.desc())
+        .load(conn)?;
+    Ok(user_notifications)
+}
+
+#[get("/api/users/{user_id}/notifications")]
+async fn get_notifications(
+    pool: web::Data<DbPool>,
+    user_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let user_notifications = web::block(move || {
+        let mut conn = pool.get()?;
+        get_user_notifications(&mut conn, *user_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(user_notifications))
+}
@@ -0,0 +1,397 @@
+use crate::controllers::campaigns::get_cached_campaigns;
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::fulfilment;
+use crate::controllers::functions;
+use crate::controllers::order_events;
+use crate::controllers::orders::{self, OrderItem};
+use crate::controllers::payments::{self, PaymentGateway};
+use crate::controllers::risk::{self, RiskLimits};
+use crate::controllers::shipping::get_shipping_method_by_code;
+use crate::controllers::webhooks;
+use actix_web::{delete, post, web, HttpResponse, Responder, Result};
+use diesel::r2d2::Pool;
+use diesel::{prelude::*, r2d2};
+use r2d2_redis::{redis, RedisConnectionManager};
+use rust_order_api::models::{Order, OrderStatus, OrderToProduct};
+use rust_order_api::schema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+#[derive(Deserialize)]
+pub struct RemoveItemQuery {
+    #[serde(default)]
+    pub quantity: Option<i32>,
+}
+
+fn get_order_totals(order: &Order) -> Value {
+    json!({
+        "subtotal": order.subtotal,
+        "shipping_fee": order.shipping_fee,
+        "price_without_discount": order.price_without_discount,
+        "discount_amount": order.discount_amount,
+        "discounted_price": order.discounted_price,
+        "campaign_id": order.campaign_id,
+    })
+}
+
+fn get_totals_diff(old_order: &Order, new_order: &Order) -> Value {
+    json!({
+        "old": get_order_totals(old_order),
+        "new": get_order_totals(new_order),
+        "difference": {
+            "subtotal": new_order.subtotal - old_order.subtotal,
+            "shipping_fee": new_order.shipping_fee - old_order.shipping_fee,
+            "price_without_discount":
+                new_order.price_without_discount - old_order.price_without_discount,
+            "discount_amount": new_order.discount_amount - old_order.discount_amount,
+            "discounted_price": new_order.discounted_price - old_order.discounted_price,
+        },
+    })
+}
+
+/// Applies `change` to the quantities of a pending order, moves the stock
+/// difference in or out of the products and re-prices the order with the
+/// current campaigns. Existing lines keep their snapshot price; only lines
+/// added by the edit are priced at today's list price. The units the edit adds,
+/// and the new price when it went up, are checked against the user's risk
+/// limits; an order that breaks one is moved to review, with the rules it was
+/// not flagged for yet added to its flags.
+fn edit_order_lines<F>(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    gateway: &dyn PaymentGateway,
+    _order_id: i32,
+    change: F,
+) -> Result<Value, DbError>
+where
+    F: FnOnce(&mut BTreeMap<i32, i32>) -> Result<(), DbError>,
+{
+    use schema::orders_products::dsl::*;
+    use schema::products::dsl::*;
+
+    let risk_limits = RiskLimits::from_env();
+    let mut risk_counted = None;
+    let mut authorization = None;
+    let edited_order = conn.transaction::<_, DbError, _>(|conn| {
+        let old_order = schema::orders::table
+            .filter(schema::orders::id.eq(_order_id))
+            .for_update()
+            .first::<Order>(conn)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", _order_id)))?;
+        if old_order.status != OrderStatus::Pending {
+            return Err(ApiError::Conflict(format!(
+                "Only pending orders can be edited, order {} is {}",
+                _order_id,
+                old_order.status.as_str()
+            ))
+            .into());
+        }
+
+        let order_lines: HashMap<i32, OrderToProduct> = OrderToProduct::belonging_to(&old_order)
+            .select(OrderToProduct::as_select())
+            .load::<OrderToProduct>(conn)?
+            .into_iter()
+            .map(|line| (line.product_id, line))
+            .collect();
+
+        let mut quantities: BTreeMap<i32, i32> = order_lines
+            .values()
+            .map(|line| (line.product_id, line.quantity))
+            .collect();
+        change(&mut quantities)?;
+        quantities.retain(|_, _quantity| *_quantity > 0);
+        if quantities.is_empty() {
+            return Err(ApiError::BadRequest(
+                "Order must contain at least one item, cancel the order instead".to_string(),
+            )
+            .into());
+        }
+
+        let mut deltas: BTreeMap<i32, i32> = BTreeMap::new();
+        for (_product_id, _quantity) in &quantities {
+            let old_quantity = order_lines.get(_product_id).map_or(0, |line| line.quantity);
+            deltas.insert(*_product_id, _quantity - old_quantity);
+        }
+        for line in order_lines.values() {
+            deltas.entry(line.product_id).or_insert(-line.quantity);
+        }
+        deltas.retain(|_, delta| *delta != 0);
+
+        let changed_ids: Vec<i32> = deltas.keys().copied().collect();
+        let locked_products: HashMap<i32, i32> = products
+            .filter(schema::products::dsl::id.eq_any(&changed_ids))
+            .select((schema::products::dsl::id, stock_quantity))
+            .order(schema::products::dsl::id)
+            .for_update()
+            .load::<(i32, i32)>(conn)?
+            .into_iter()
+            .collect();
+
+        let missing_ids: Vec<i32> = changed_ids
+            .iter()
+            .filter(|_product_id| !locked_products.contains_key(_product_id))
+            .copied()
+            .collect();
+        if !missing_ids.is_empty() {
+            return Err(
+                ApiError::NotFound(format!("Products not found: {:?}", missing_ids)).into(),
+            );
+        }
+
+        let out_of_stock_ids: Vec<i32> = deltas
+            .iter()
+            .filter(|(_product_id, delta)| locked_products[_product_id] < **delta)
+            .map(|(_product_id, _)| *_product_id)
+            .collect();
+        if !out_of_stock_ids.is_empty() {
+            return Err(ApiError::OutOfStock(out_of_stock_ids).into());
+        }
+
+        for (_product_id, delta) in &deltas {
+            diesel::update(products)
+                .filter(schema::products::dsl::id.eq(_product_id))
+                .set(stock_quantity.eq(stock_quantity - delta))
+                .execute(conn)?;
+            let previous_stock = locked_products[_product_id];
+            webhooks::publish_stock_change(
+                conn,
+                *_product_id,
+                previous_stock,
+                previous_stock - delta,
+            )?;
+        }
+
+        let items: Vec<OrderItem> = quantities
+            .iter()
+            .map(|(_product_id, _quantity)| OrderItem {
+                product_id: *_product_id,
+                quantity: *_quantity,
+            })
+            .collect();
+        let mut order_products = orders::load_order_products(conn, &items)?;
+        for order_product in &mut order_products {
+            if let Some(line) = order_lines.get(&order_product.product.id) {
+                order_product.product.list_price = line.unit_price;
+                order_product.product.title = line.title.clone();
+                order_product.product.author = line.author.clone();
+                order_product.category_title = line.category_title.clone();
+            }
+        }
+
+        let all_campaigns = get_cached_campaigns(conn, redis_conn)?;
+        let order_shipping_method = get_shipping_method_by_code(conn, &old_order.shipping_method)?;
+        let pricing =
+            functions::get_order_pricing(all_campaigns, &order_products, order_shipping_method)?;
+
+        let added_items: Vec<OrderItem> = deltas
+            .iter()
+            .filter(|(_, delta)| **delta > 0)
+            .map(|(_product_id, delta)| OrderItem {
+                product_id: *_product_id,
+                quantity: *delta,
+            })
+            .collect();
+        let new_risk_flags = risk::check_order(
+            redis_conn,
+            &risk_limits,
+            old_order.user_id,
+            false,
+            &added_items,
+            (pricing.discounted_price > old_order.discounted_price)
+                .then_some(pricing.discounted_price),
+        )?;
+        risk_counted = Some((old_order.user_id, added_items));
+        let mut order_risk_flags = match &old_order.risk_flags {
+            Some(Value::Array(flags)) => flags.clone(),
+            _ => vec![],
+        };
+        risk::add_risk_flags(&mut order_risk_flags, &new_risk_flags);
+
+        let mut new_order: Order = diesel::update(schema::orders::table)
+            .filter(schema::orders::id.eq(_order_id))
+            .set((
+                schema::orders::subtotal.eq(pricing.subtotal),
+                schema::orders::shipping_fee.eq(pricing.shipping_cost),
+                schema::orders::price_without_discount.eq(pricing.price_without_discount),
+                schema::orders::discount_amount.eq(pricing.discount_amount()),
+                schema::orders::discounted_price.eq(pricing.discounted_price),
+                schema::orders::campaign_id.eq(pricing.campaign_id),
+                schema::orders::pricing_rules.eq(pricing.pricing_rules()),
+                schema::orders::risk_flags
+                    .eq((!order_risk_flags.is_empty()).then(|| json!(order_risk_flags))),
+            ))
+            .get_result(conn)?;
+
+        for order_product in &order_products {
+            let _product_id = order_product.product.id;
+            if !deltas.contains_key(&_product_id) {
+                continue;
+            }
+            if order_lines.contains_key(&_product_id) {
+                diesel::update(orders_products)
+                    .filter(order_id.eq(_order_id))
+                    .filter(product_id.eq(_product_id))
+                    .set(quantity.eq(order_product.quantity))
+                    .execute(conn)?;
+            } else {
+                diesel::insert_into(orders_products)
+                    .values((
+                        order_id.eq(_order_id),
+                        product_id.eq(_product_id),
+                        quantity.eq(order_product.quantity),
+                        unit_price.eq(order_product.product.list_price),
+                        schema::orders_products::title.eq(&order_product.product.title),
+                        schema::orders_products::author.eq(&order_product.product.author),
+                        category_title.eq(&order_product.category_title),
+                    ))
+                    .execute(conn)?;
+            }
+        }
+        let removed_ids: Vec<i32> = order_lines
+            .keys()
+            .filter(|_product_id| !quantities.contains_key(_product_id))
+            .copied()
+            .collect();
+        diesel::delete(orders_products)
+            .filter(order_id.eq(_order_id))
+            .filter(product_id.eq_any(&removed_ids))
+            .execute(conn)?;
+        fulfilment::update_user_total_spent(
+            conn,
+            new_order.user_id,
+            new_order.discounted_price - old_order.discounted_price,
+        )?;
+        payments::reauthorize_order_payment(conn, gateway, &new_order, &mut authorization)?;
+        if new_risk_flags.is_empty() {
+            order_events::publish(conn, order_events::ORDER_UPDATED, &new_order)?;
+        } else {
+            new_order = orders::transition_order_status(conn, _order_id, OrderStatus::Review)?;
+        }
+
+        Ok(json!({
+            "order": orders::get_order_by_id(conn, _order_id)?,
+            "diff": get_totals_diff(&old_order, &new_order),
+        }))
+    });
+
+    if edited_order.is_err() {
+        if let Some((_user_id, added_items)) = risk_counted {
+            if let Err(err) =
+                risk::release_order(redis_conn, &risk_limits, _user_id, false, &added_items)
+            {
+                tracing::error!(
+                    "Could not release risk counters of user {}: {}",
+                    _user_id,
+                    err
+                );
+            }
+        }
+        if let Some(reference) = authorization {
+            payments::void_after_rollback(conn, &reference);
+        }
+    }
+    edited_order
+}
+
+pub fn add_order_item(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    gateway: &dyn PaymentGateway,
+    _order_id: i32,
+    item: &OrderItem,
+) -> Result<Value, DbError> {
+    if item.quantity < 1 {
+        return Err(ApiError::BadRequest(format!(
+            "Quantity for product {} must be at least 1",
+            item.product_id
+        ))
+        .into());
+    }
+    edit_order_lines(conn, redis_conn, gateway, _order_id, |quantities| {
+        orders::add_item_quantity(quantities, item)?;
+        Ok(())
+    })
+}
+
+pub fn remove_order_item(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    gateway: &dyn PaymentGateway,
+    _order_id: i32,
+    _product_id: i32,
+    _quantity: Option<i32>,
+) -> Result<Value, DbError> {
+    edit_order_lines(conn, redis_conn, gateway, _order_id, |quantities| {
+        let line_quantity = quantities.get_mut(&_product_id).ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Product {} is not in order {}",
+                _product_id, _order_id
+            ))
+        })?;
+        match _quantity {
+            Some(_quantity) if _quantity < 1 || _quantity > *line_quantity => {
+                Err(ApiError::BadRequest(format!(
+                    "Quantity to remove for product {} must be between 1 and {}",
+                    _product_id, line_quantity
+                ))
+                .into())
+            }
+            Some(_quantity) => {
+                *line_quantity -= _quantity;
+                Ok(())
+            }
+            None => {
+                *line_quantity = 0;
+                Ok(())
+            }
+        }
+    })
+}
+
+#[post("/api/orders/{order_id}/items")]
+async fn add_item(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    gateway: web::Data<dyn PaymentGateway>,
+    order_id: web::Path<i32>,
+    form: web::Json<OrderItem>,
+) -> Result<impl Responder> {
+    let order = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        add_order_item(&mut db_conn, &mut redis_conn, &**gateway, *order_id, &form)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order))
+}
+
+#[delete("/api/orders/{order_id}/items/{product_id}")]
+async fn remove_item(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    gateway: web::Data<dyn PaymentGateway>,
+    path: web::Path<(i32, i32)>,
+    query: web::Query<RemoveItemQuery>,
+) -> Result<impl Responder> {
+    let (_order_id, _product_id) = path.into_inner();
+    let order = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        remove_order_item(
+            &mut db_conn,
+            &mut redis_conn,
+            &**gateway,
+            _order_id,
+            _product_id,
+            query.quantity,
+        )
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order))
+}
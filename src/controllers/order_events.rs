@@ -0,0 +1,134 @@
+use crate::controllers::outbox;
+use actix_web::{get, http::header, rt, web, HttpResponse, Responder};
+use apalis::prelude::Job;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use futures::Stream;
+use rust_order_api::models::{Money, Order, OrderStatus};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const ORDER_CREATED: &str = "order.created";
+pub const ORDER_UPDATED: &str = "order.updated";
+
+const CHANNEL_CAPACITY: usize = 1024;
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A change to an order, streamed to `GET /api/order_events`. Events travel
+/// through the outbox like jobs do, so they are only streamed once the
+/// transaction that changed the order has committed.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct OrderEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub order_id: i32,
+    pub user_id: i32,
+    pub status: OrderStatus,
+    pub price_without_discount: Money,
+    pub discounted_price: Money,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Job for OrderEvent {
+    const NAME: &'static str = "OrderEvent";
+}
+
+/// Queues an `event_type` event carrying the order's current status and
+/// prices.
+pub fn publish(conn: &mut PgConnection, event_type: &str, order: &Order) -> Result<(), DbError> {
+    outbox::enqueue(
+        conn,
+        &OrderEvent {
+            event_type: event_type.to_string(),
+            order_id: order.id,
+            user_id: order.user_id,
+            status: order.status,
+            price_without_discount: order.price_without_discount,
+            discounted_price: order.discounted_price,
+            updated_at: order.updated_at,
+        },
+    )?;
+    Ok(())
+}
+
+/// Fans the order events relayed by this process out to the open streams.
+/// Each event carries the id of its outbox message.
+#[derive(Clone)]
+pub struct OrderEventHub {
+    sender: broadcast::Sender<(i32, OrderEvent)>,
+}
+
+impl OrderEventHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        OrderEventHub { sender }
+    }
+
+    /// Events sent while nobody is listening are dropped.
+    pub fn send(&self, event_id: i32, event: OrderEvent) {
+        let _ = self.sender.send((event_id, event));
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<(i32, OrderEvent)> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for OrderEventHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn get_event_chunk(event_id: i32, event: &OrderEvent) -> Option<String> {
+    Some(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event_id,
+        event.event_type,
+        serde_json::to_string(event).ok()?
+    ))
+}
+
+/// Turns the hub's events into Server-Sent Events. A stream that falls too far
+/// behind gets a `resync` event with the number of events it missed.
+fn get_event_stream(
+    receiver: broadcast::Receiver<(i32, OrderEvent)>,
+    filter_user_id: Option<i32>,
+) -> impl Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    futures::stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            let chunk = match rt::time::timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+                Err(_) => ": keep-alive\n\n".to_string(),
+                Ok(Ok((event_id, event))) => {
+                    if filter_user_id.is_some_and(|user_id| user_id != event.user_id) {
+                        continue;
+                    }
+                    get_event_chunk(event_id, &event)?
+                }
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    format!("event: resync\ndata: {{\"skipped\":{}}}\n\n", skipped)
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            return Some((Ok(web::Bytes::from(chunk)), receiver));
+        }
+    })
+}
+
+#[derive(Deserialize)]
+pub struct OrderEventQuery {
+    pub user_id: Option<i32>,
+}
+
+#[get("/api/order_events")]
+async fn stream_order_events(
+    hub: web::Data<OrderEventHub>,
+    query: web::Query<OrderEventQuery>,
+) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(get_event_stream(hub.subscribe(), query.user_id))
+}
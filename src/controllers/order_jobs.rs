@@ -0,0 +1,585 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::fulfilment;
+use crate::controllers::orders::{self, OrderDto};
+use crate::controllers::outbox;
+use crate::controllers::payments::PaymentGateway;
+use crate::controllers::retry::RetryPolicy;
+use crate::insertables::{NewDeadLetterJob, NewOrderJob};
+use crate::{QueryOrder, SubmitOrder};
+use actix_web::{get, post, web, HttpResponse, Responder, Result};
+use apalis::prelude::*;
+use diesel::dsl::{now, IntervalDsl};
+use diesel::r2d2::Pool;
+use diesel::{prelude::*, r2d2};
+use r2d2_redis::{redis, RedisConnectionManager};
+use rust_order_api::models::{DeadLetterJob, DeadLetterStatus, OrderJob, OrderJobStatus};
+use rust_order_api::schema;
+use schema::dead_letter_jobs::dsl::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+const DEFAULT_STALE_SECONDS: i32 = 5 * 60;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    #[serde(default)]
+    pub status: Option<DeadLetterStatus>,
+}
+
+/// Fulfils the order, retrying with backoff as the policy allows. When the
+/// last attempt fails the job is stored in `dead_letter_jobs` and the error
+/// is returned to the worker.
+pub async fn process_order_job(
+    pool: DbPool,
+    retry_policy: &RetryPolicy,
+    job: QueryOrder,
+    _job_id: String,
+) -> Result<(), DbError> {
+    let (result, _attempts) = retry_policy
+        .retry(|_| {
+            let fulfil_pool = pool.clone();
+            let fulfil_job = job.clone();
+            async move {
+                web::block(move || {
+                    let mut conn = fulfil_pool.get()?;
+                    fulfilment::fulfil_order(&mut conn, &fulfil_job)
+                })
+                .await?
+            }
+        })
+        .await;
+
+    if let Err(err) = result {
+        let _error = err.to_string();
+        web::block(move || {
+            let mut conn = pool.get()?;
+            insert_dead_letter_job(
+                &mut conn,
+                QueryOrder::NAME,
+                &_job_id,
+                serde_json::to_value(&job)?,
+                &_error,
+                _attempts,
+            )
+        })
+        .await??;
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub fn insert_dead_letter_job(
+    conn: &mut PgConnection,
+    _job_name: &str,
+    _job_id: &str,
+    _payload: Value,
+    _error: &str,
+    _attempts: i32,
+) -> Result<DeadLetterJob, DbError> {
+    let new_dead_letter_job = NewDeadLetterJob {
+        job_name: _job_name.to_string(),
+        job_id: _job_id.to_string(),
+        payload: _payload,
+        error: _error.to_string(),
+        attempts: _attempts,
+    };
+    let dead_letter_job = diesel::insert_into(dead_letter_jobs)
+        .values(&new_dead_letter_job)
+        .returning(DeadLetterJob::as_returning())
+        .get_result(conn)?;
+    Ok(dead_letter_job)
+}
+
+pub fn get_all_dead_letter_jobs(
+    conn: &mut PgConnection,
+    query: &DeadLetterQuery,
+) -> Result<Vec<DeadLetterJob>, DbError> {
+    let mut dead_letter_query = dead_letter_jobs
+        .select(DeadLetterJob::as_select())
+        .order(id.desc())
+        .into_boxed();
+    if let Some(_status) = query.status {
+        dead_letter_query = dead_letter_query.filter(status.eq(_status));
+    }
+    Ok(dead_letter_query.load(conn)?)
+}
+
+pub fn get_dead_letter_job_by_id(
+    conn: &mut PgConnection,
+    dead_letter_job_id: i32,
+) -> Result<DeadLetterJob, DbError> {
+    let dead_letter_job = dead_letter_jobs
+        .filter(id.eq(dead_letter_job_id))
+        .select(DeadLetterJob::as_select())
+        .first::<DeadLetterJob>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Dead letter job {} not found", dead_letter_job_id))
+        })?;
+    Ok(dead_letter_job)
+}
+
+fn lock_dead_job(
+    conn: &mut PgConnection,
+    dead_letter_job_id: i32,
+) -> Result<DeadLetterJob, DbError> {
+    let dead_letter_job = dead_letter_jobs
+        .filter(id.eq(dead_letter_job_id))
+        .select(DeadLetterJob::as_select())
+        .for_update()
+        .first::<DeadLetterJob>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Dead letter job {} not found", dead_letter_job_id))
+        })?;
+    if dead_letter_job.status != DeadLetterStatus::Dead {
+        return Err(ApiError::Conflict(format!(
+            "Dead letter job {} is already {}",
+            dead_letter_job_id,
+            dead_letter_job.status.as_str()
+        ))
+        .into());
+    }
+    Ok(dead_letter_job)
+}
+
+fn resolve_dead_letter_job(
+    conn: &mut PgConnection,
+    dead_letter_job_id: i32,
+    next_status: DeadLetterStatus,
+) -> Result<DeadLetterJob, DbError> {
+    let dead_letter_job = diesel::update(dead_letter_jobs)
+        .filter(id.eq(dead_letter_job_id))
+        .set((status.eq(next_status), resolved_at.eq(diesel::dsl::now)))
+        .returning(DeadLetterJob::as_returning())
+        .get_result(conn)?;
+    Ok(dead_letter_job)
+}
+
+/// Puts the job back on its queue, through the outbox, as a new job with a
+/// fresh set of attempts. The workers skip work they already did, so a job
+/// that is retried twice does no harm.
+pub fn retry_dead_letter_job(
+    conn: &mut PgConnection,
+    dead_letter_job_id: i32,
+) -> Result<DeadLetterJob, DbError> {
+    conn.transaction::<_, DbError, _>(|conn| {
+        let dead_letter_job = lock_dead_job(conn, dead_letter_job_id)?;
+        if dead_letter_job.job_name == SubmitOrder::NAME {
+            let job = serde_json::from_value::<SubmitOrder>(dead_letter_job.payload.clone())?;
+            requeue_order_job(conn, job.order_job_id)?;
+        }
+        outbox::enqueue_payload(conn, &dead_letter_job.job_name, dead_letter_job.payload)?;
+        resolve_dead_letter_job(conn, dead_letter_job_id, DeadLetterStatus::Retried)
+    })
+}
+
+/// Drops the job for good. For an order job the stock reserved for its order
+/// is given back and the order is cancelled, since nothing will ever fulfil
+/// it. A dropped order submission leaves its order job failed.
+pub fn discard_dead_letter_job(
+    conn: &mut PgConnection,
+    gateway: &dyn PaymentGateway,
+    dead_letter_job_id: i32,
+) -> Result<DeadLetterJob, DbError> {
+    conn.transaction::<_, DbError, _>(|conn| {
+        let dead_letter_job = lock_dead_job(conn, dead_letter_job_id)?;
+        if dead_letter_job.job_name == QueryOrder::NAME {
+            let job = serde_json::from_value::<QueryOrder>(dead_letter_job.payload)?;
+            fulfilment::release_order(
+                conn,
+                gateway,
+                &job,
+                &format!("Order job failed: {}", dead_letter_job.error),
+            )?;
+        }
+        resolve_dead_letter_job(conn, dead_letter_job_id, DeadLetterStatus::Discarded)
+    })
+}
+
+fn insert_order_job(conn: &mut PgConnection, order: &OrderDto) -> Result<OrderJob, DbError> {
+    let new_order_job = NewOrderJob {
+        payload: serde_json::to_value(order)?,
+    };
+    let order_job = diesel::insert_into(schema::order_jobs::table)
+        .values(&new_order_job)
+        .returning(OrderJob::as_returning())
+        .get_result(conn)?;
+    Ok(order_job)
+}
+
+pub fn get_order_job_by_id(
+    conn: &mut PgConnection,
+    order_job_id: i32,
+) -> Result<OrderJob, DbError> {
+    let order_job = schema::order_jobs::table
+        .filter(schema::order_jobs::id.eq(order_job_id))
+        .select(OrderJob::as_select())
+        .first::<OrderJob>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Order job {} not found", order_job_id)))?;
+    Ok(order_job)
+}
+
+/// Claims a queued order job for the worker. Returns `None` when the job was
+/// already picked up, so a job that is delivered twice places one order.
+fn start_order_job(
+    conn: &mut PgConnection,
+    order_job_id: i32,
+) -> Result<Option<OrderJob>, DbError> {
+    let order_job = diesel::update(schema::order_jobs::table)
+        .filter(schema::order_jobs::id.eq(order_job_id))
+        .filter(schema::order_jobs::status.eq(OrderJobStatus::Queued))
+        .set((
+            schema::order_jobs::status.eq(OrderJobStatus::Running),
+            schema::order_jobs::started_at.eq(now),
+        ))
+        .returning(OrderJob::as_returning())
+        .get_result(conn)
+        .optional()?;
+    Ok(order_job)
+}
+
+/// Records how a claimed order job ended: the id of the placed order, or the
+/// error body the synchronous endpoint would have responded with. Returns
+/// `None` when the job was claimed again since, in which case the later run
+/// owns the outcome.
+fn finish_order_job(
+    conn: &mut PgConnection,
+    order_job: &OrderJob,
+    outcome: Result<i32, Value>,
+) -> Result<Option<OrderJob>, DbError> {
+    let (next_status, created_order_id, order_error) = match outcome {
+        Ok(created_order_id) => (OrderJobStatus::Succeeded, Some(created_order_id), None),
+        Err(order_error) => (OrderJobStatus::Failed, None, Some(order_error)),
+    };
+    let finished_order_job = diesel::update(schema::order_jobs::table)
+        .filter(schema::order_jobs::id.eq(order_job.id))
+        .filter(schema::order_jobs::status.eq(OrderJobStatus::Running))
+        .filter(schema::order_jobs::started_at.eq(order_job.started_at))
+        .set((
+            schema::order_jobs::status.eq(next_status),
+            schema::order_jobs::order_id.eq(created_order_id),
+            schema::order_jobs::error.eq(order_error),
+            schema::order_jobs::finished_at.eq(now),
+        ))
+        .returning(OrderJob::as_returning())
+        .get_result(conn)
+        .optional()?;
+    Ok(finished_order_job)
+}
+
+/// Hands a claimed order job back to the queue after a failed attempt, so the
+/// next attempt can claim it.
+fn release_order_job(conn: &mut PgConnection, order_job: &OrderJob) -> Result<(), DbError> {
+    diesel::update(schema::order_jobs::table)
+        .filter(schema::order_jobs::id.eq(order_job.id))
+        .filter(schema::order_jobs::status.eq(OrderJobStatus::Running))
+        .filter(schema::order_jobs::started_at.eq(order_job.started_at))
+        .set((
+            schema::order_jobs::status.eq(OrderJobStatus::Queued),
+            schema::order_jobs::started_at.eq(None::<chrono::NaiveDateTime>),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Marks a queued order job failed once its submission is dead-lettered.
+fn fail_order_job(
+    conn: &mut PgConnection,
+    order_job_id: i32,
+    order_error: Value,
+) -> Result<(), DbError> {
+    diesel::update(schema::order_jobs::table)
+        .filter(schema::order_jobs::id.eq(order_job_id))
+        .filter(schema::order_jobs::status.eq(OrderJobStatus::Queued))
+        .set((
+            schema::order_jobs::status.eq(OrderJobStatus::Failed),
+            schema::order_jobs::error.eq(order_error),
+            schema::order_jobs::finished_at.eq(now),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Queues a failed order job again when its dead-lettered submission is
+/// retried.
+fn requeue_order_job(conn: &mut PgConnection, order_job_id: i32) -> Result<(), DbError> {
+    diesel::update(schema::order_jobs::table)
+        .filter(schema::order_jobs::id.eq(order_job_id))
+        .filter(schema::order_jobs::status.eq(OrderJobStatus::Failed))
+        .set((
+            schema::order_jobs::status.eq(OrderJobStatus::Queued),
+            schema::order_jobs::error.eq(None::<Value>),
+            schema::order_jobs::started_at.eq(None::<chrono::NaiveDateTime>),
+            schema::order_jobs::finished_at.eq(None::<chrono::NaiveDateTime>),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Stores the order request as a queued order job and hands it to the
+/// `submit_order_service` worker through the outbox; the worker prices and
+/// inserts the order later. Malformed item lists are rejected right away;
+/// everything else, such as missing products or stock, is reported on the job.
+/// `on_created` is run on the job before the transaction commits.
+pub fn submit_order<F>(
+    conn: &mut PgConnection,
+    order: OrderDto,
+    on_created: F,
+) -> Result<OrderJob, DbError>
+where
+    F: FnOnce(&mut PgConnection, &Value) -> Result<(), DbError>,
+{
+    orders::merge_order_items(&order.line_items())?;
+    conn.transaction::<_, DbError, _>(|conn| {
+        let order_job = insert_order_job(conn, &order)?;
+        outbox::enqueue(
+            conn,
+            &SubmitOrder {
+                order_job_id: order_job.id,
+            },
+        )?;
+        on_created(conn, &json!(order_job))?;
+        Ok(order_job)
+    })
+}
+
+/// Inserts the order and marks the job succeeded in the same transaction, so
+/// a job is never left without the order it placed or the other way round.
+fn place_order(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    gateway: &dyn PaymentGateway,
+    order_job: &OrderJob,
+) -> Result<(), DbError> {
+    let order = serde_json::from_value::<OrderDto>(order_job.payload.clone())?;
+    orders::insert_new_order_with(conn, redis_conn, gateway, order, |conn, created_order| {
+        let created_order_id = created_order["id"]
+            .as_i64()
+            .map(|created_order_id| created_order_id as i32)
+            .ok_or("Created order has no id")?;
+        finish_order_job(conn, order_job, Ok(created_order_id))?.ok_or_else(|| {
+            ApiError::Conflict(format!("Order job {} was claimed again", order_job.id))
+        })?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Errors the synchronous endpoint would respond with reject the order for
+/// good, except an unreachable payment gateway. Anything else may pass on
+/// another attempt.
+fn is_rejection(err: &DbError) -> bool {
+    matches!(
+        err.downcast_ref::<ApiError>(),
+        Some(api_error) if !matches!(api_error, ApiError::PaymentGatewayError(_))
+    )
+}
+
+/// Places the order of a queued order job. A rejected order fails the job,
+/// not the worker; any other error puts the job back in the queue and is
+/// returned, so the attempt can be retried.
+pub fn process_submit_order_job(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    gateway: &dyn PaymentGateway,
+    order_job_id: i32,
+) -> Result<(), DbError> {
+    let Some(order_job) = start_order_job(conn, order_job_id)? else {
+        return Ok(());
+    };
+    match place_order(conn, redis_conn, gateway, &order_job) {
+        Ok(()) => Ok(()),
+        Err(err) if is_rejection(&err) => {
+            finish_order_job(conn, &order_job, Err(errors::get_error_json(&err)))?;
+            Ok(())
+        }
+        Err(err) => {
+            release_order_job(conn, &order_job)?;
+            Err(err)
+        }
+    }
+}
+
+/// Runs the submission with retries and backoff as the policy allows. When
+/// the last attempt fails the submission is stored in `dead_letter_jobs`, the
+/// order job is marked failed and the error is returned to the worker.
+pub async fn process_submit_order(
+    pool: DbPool,
+    redis_pool: Pool<RedisConnectionManager>,
+    gateway: Arc<dyn PaymentGateway>,
+    retry_policy: &RetryPolicy,
+    job: SubmitOrder,
+    _job_id: String,
+) -> Result<(), DbError> {
+    let (result, _attempts) = retry_policy
+        .retry(|_| {
+            let submit_pool = pool.clone();
+            let redis_pool = redis_pool.clone();
+            let gateway = gateway.clone();
+            let order_job_id = job.order_job_id;
+            async move {
+                web::block(move || {
+                    let mut conn = submit_pool.get()?;
+                    let mut redis_conn = redis_pool.get()?;
+                    process_submit_order_job(&mut conn, &mut redis_conn, &*gateway, order_job_id)
+                })
+                .await?
+            }
+        })
+        .await;
+
+    if let Err(err) = result {
+        let _error = err.to_string();
+        let order_error = errors::get_error_json(&err);
+        web::block(move || {
+            let mut conn = pool.get()?;
+            conn.transaction::<_, DbError, _>(|conn| {
+                fail_order_job(conn, job.order_job_id, order_error)?;
+                insert_dead_letter_job(
+                    conn,
+                    SubmitOrder::NAME,
+                    &_job_id,
+                    serde_json::to_value(&job)?,
+                    &_error,
+                    _attempts,
+                )
+            })
+        })
+        .await??;
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Puts order jobs that have been running for more than `stale_seconds` back
+/// in the queue, on the assumption that their worker died. A run that still
+/// finishes later cannot record its outcome, which rolls its order back.
+pub fn requeue_stale_order_jobs(
+    conn: &mut PgConnection,
+    stale_seconds: i32,
+) -> Result<usize, DbError> {
+    conn.transaction::<_, DbError, _>(|conn| {
+        let stale_job_ids: Vec<i32> = diesel::update(schema::order_jobs::table)
+            .filter(schema::order_jobs::status.eq(OrderJobStatus::Running))
+            .filter(schema::order_jobs::started_at.lt((now - stale_seconds.seconds()).nullable()))
+            .set((
+                schema::order_jobs::status.eq(OrderJobStatus::Queued),
+                schema::order_jobs::started_at.eq(None::<chrono::NaiveDateTime>),
+            ))
+            .returning(schema::order_jobs::id)
+            .get_results(conn)?;
+        for order_job_id in &stale_job_ids {
+            outbox::enqueue(
+                conn,
+                &SubmitOrder {
+                    order_job_id: *order_job_id,
+                },
+            )?;
+        }
+        Ok(stale_job_ids.len())
+    })
+}
+
+/// Requeues stale order jobs about once a minute until the process stops.
+/// Reads ORDER_JOB_STALE_SECONDS, falling back to five minutes.
+pub async fn run_order_job_sweeper(pool: DbPool) -> std::io::Result<()> {
+    let stale_seconds = env::var("ORDER_JOB_STALE_SECONDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_STALE_SECONDS)
+        .max(1);
+    loop {
+        let sweep_pool = pool.clone();
+        let requeued = web::block(move || {
+            let mut conn = sweep_pool.get()?;
+            requeue_stale_order_jobs(&mut conn, stale_seconds)
+        })
+        .await;
+        match requeued {
+            Ok(Ok(0)) => {}
+            Ok(Ok(requeued)) => tracing::warn!("Requeued {} stale order jobs", requeued),
+            Ok(Err(err)) => tracing::error!("Order job sweeper error: {}", err),
+            Err(err) => tracing::error!("Order job sweeper error: {}", err),
+        }
+        actix_web::rt::time::sleep(SWEEP_INTERVAL).await;
+    }
+}
+
+#[get("/api/order_jobs/{order_job_id}")]
+async fn get_order_job(
+    pool: web::Data<DbPool>,
+    order_job_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let order_job = web::block(move || {
+        let mut conn = pool.get()?;
+        get_order_job_by_id(&mut conn, *order_job_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order_job))
+}
+
+#[get("/api/dead_letter_jobs")]
+async fn get_dead_letter_jobs(
+    pool: web::Data<DbPool>,
+    query: web::Query<DeadLetterQuery>,
+) -> Result<impl Responder> {
+    let all_dead_letter_jobs = web::block(move || {
+        let mut conn = pool.get()?;
+        get_all_dead_letter_jobs(&mut conn, &query)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(all_dead_letter_jobs))
+}
+
+#[get("/api/dead_letter_jobs/{dead_letter_job_id}")]
+async fn get_dead_letter_job(
+    pool: web::Data<DbPool>,
+    dead_letter_job_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let dead_letter_job = web::block(move || {
+        let mut conn = pool.get()?;
+        get_dead_letter_job_by_id(&mut conn, *dead_letter_job_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(dead_letter_job))
+}
+
+#[post("/api/dead_letter_jobs/{dead_letter_job_id}/retry")]
+async fn retry_dead_letter(
+    pool: web::Data<DbPool>,
+    dead_letter_job_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let dead_letter_job = web::block(move || {
+        let mut conn = pool.get()?;
+        retry_dead_letter_job(&mut conn, *dead_letter_job_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(dead_letter_job))
+}
+
+#[post("/api/dead_letter_jobs/{dead_letter_job_id}/discard")]
+async fn discard_dead_letter(
+    pool: web::Data<DbPool>,
+    gateway: web::Data<dyn PaymentGateway>,
+    dead_letter_job_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let dead_letter_job = web::block(move || {
+        let mut conn = pool.get()?;
+        discard_dead_letter_job(&mut conn, &**gateway, *dead_letter_job_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(dead_letter_job))
+}
@@ -1,92 +1,369 @@
+use crate::controllers::addresses::{get_address_by_id, get_address_snapshot};
+use crate::controllers::campaigns::get_cached_campaigns;
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::fulfilment;
 use crate::controllers::functions;
-use crate::insertables::NewOrder;
+use crate::controllers::idempotency::{self, IdempotencyState};
+use crate::controllers::order_events;
+use crate::controllers::order_jobs;
+use crate::controllers::outbox;
+use crate::controllers::payments::{self, PaymentGateway};
+use crate::controllers::risk::{self, RiskLimits};
+use crate::controllers::shipping::get_active_shipping_method;
+use crate::controllers::webhooks;
+use crate::insertables::{NewOrder, NewOrderLine};
 use crate::QueryOrder;
-use actix_web::{delete, error, get, post, web, HttpResponse, Responder, Result};
-use diesel::dsl::sql;
+use actix_web::{
+    delete, error, get, patch, post, rt, web, HttpRequest, HttpResponse, HttpResponseBuilder,
+    Responder, Result,
+};
+use chrono::NaiveDateTime;
+use diesel::pg::Pg;
 use diesel::r2d2::{Pool, PooledConnection};
-use diesel::sql_types::Text;
 use diesel::{prelude::*, r2d2};
-use futures::TryFutureExt;
-use rust_order_api::models::{Order, Product};
+use rust_order_api::models::{
+    Money, Order, OrderStatus, OrderStatusHistory, OrderToProduct, Product,
+};
 use rust_order_api::schema::{self};
 use schema::orders::dsl::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 type DbError = Box<dyn std::error::Error + Send + Sync>;
 type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
-use apalis::prelude::*;
-use apalis::redis::RedisStorage;
 use r2d2_redis::{redis, RedisConnectionManager};
 
+const DEFAULT_PAGE_LIMIT: i64 = 20;
+const MAX_PAGE_LIMIT: i64 = 100;
+const MAX_PAGE: i64 = 100_000;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OrderDto {
+    pub user_id: i32,
+    #[serde(default)]
+    pub items: Vec<OrderItem>,
+    #[serde(default)]
+    pub product_ids: Vec<i32>,
+    #[serde(default)]
+    pub shipping_method: Option<String>,
+    pub shipping_address_id: i32,
+    #[serde(default)]
+    pub billing_address_id: Option<i32>,
+    #[serde(default)]
+    pub payment_method: Option<String>,
+}
+
+impl OrderDto {
+    pub fn line_items(&self) -> Vec<OrderItem> {
+        get_line_items(&self.items, &self.product_ids)
+    }
+}
+
 #[derive(Deserialize)]
-struct OrderDto {
-    user_id: i32,
-    product_ids: Vec<i32>,
+pub struct CreateOrderQuery {
+    #[serde(default, rename = "async")]
+    pub is_async: bool,
+}
+
+pub fn get_line_items(items: &[OrderItem], _product_ids: &[i32]) -> Vec<OrderItem> {
+    let mut line_items = items.to_vec();
+    line_items.extend(_product_ids.iter().map(|_product_id| OrderItem {
+        product_id: *_product_id,
+        quantity: 1,
+    }));
+    line_items
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderSortField {
+    Id,
+    CreatedAt,
+    DiscountedPrice,
+    PriceWithoutDiscount,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub struct OrderQuery {
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+    pub user_id: Option<i32>,
+    pub campaign_id: Option<i32>,
+    pub status: Option<OrderStatus>,
+    pub min_price: Option<Money>,
+    pub max_price: Option<Money>,
+    pub created_from: Option<NaiveDateTime>,
+    pub created_to: Option<NaiveDateTime>,
+    pub sort_by: Option<OrderSortField>,
+    pub direction: Option<SortDirection>,
+}
+
+#[derive(Deserialize)]
+struct OrderStatusDto {
+    status: OrderStatus,
+}
+
+#[derive(Deserialize)]
+struct CancelOrderDto {
+    cancelled_by: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OrderItem {
+    pub product_id: i32,
+    pub quantity: i32,
 }
 
 #[derive(Queryable, Debug)]
 pub struct ProductWithCategory {
     pub product: Product,
     pub category_title: String,
+    pub quantity: i32,
+}
+
+pub fn load_order_products(
+    conn: &mut PgConnection,
+    items: &[OrderItem],
+) -> Result<Vec<ProductWithCategory>, DbError> {
+    use schema::categories::dsl::*;
+    use schema::products::dsl::*;
+
+    let _product_ids: Vec<i32> = items.iter().map(|item| item.product_id).collect();
+    let order_products: Vec<ProductWithCategory> = products
+        .filter(schema::products::dsl::id.eq_any(&_product_ids))
+        .inner_join(categories)
+        .select((Product::as_select(), schema::categories::title))
+        .order(schema::products::dsl::id)
+        .load::<(Product, String)>(conn)?
+        .into_iter()
+        .map(|(product, category_title)| {
+            let quantity = items
+                .iter()
+                .find(|item| item.product_id == product.id)
+                .map_or(0, |item| item.quantity);
+            ProductWithCategory {
+                product,
+                category_title,
+                quantity,
+            }
+        })
+        .collect();
+
+    let missing_ids: Vec<i32> = _product_ids
+        .iter()
+        .filter(|_product_id| {
+            !order_products
+                .iter()
+                .any(|product| product.product.id == **_product_id)
+        })
+        .copied()
+        .collect();
+    if !missing_ids.is_empty() {
+        return Err(ApiError::NotFound(format!("Products not found: {:?}", missing_ids)).into());
+    }
+
+    Ok(order_products)
+}
+
+/// Adds the item's quantity to its product's total, rejecting totals that do
+/// not fit a line.
+pub fn add_item_quantity(
+    quantities: &mut BTreeMap<i32, i32>,
+    item: &OrderItem,
+) -> Result<(), ApiError> {
+    let line_quantity = quantities.entry(item.product_id).or_insert(0);
+    *line_quantity = line_quantity.checked_add(item.quantity).ok_or_else(|| {
+        ApiError::BadRequest(format!(
+            "Quantity for product {} is too large",
+            item.product_id
+        ))
+    })?;
+    Ok(())
+}
+
+pub fn merge_order_items(items: &[OrderItem]) -> Result<Vec<OrderItem>, ApiError> {
+    if items.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Order must contain at least one item".to_string(),
+        ));
+    }
+    let mut quantities: BTreeMap<i32, i32> = BTreeMap::new();
+    for item in items {
+        if item.quantity < 1 {
+            return Err(ApiError::BadRequest(format!(
+                "Quantity for product {} must be at least 1",
+                item.product_id
+            )));
+        }
+        add_item_quantity(&mut quantities, item)?;
+    }
+    Ok(quantities
+        .into_iter()
+        .map(|(_product_id, _quantity)| OrderItem {
+            product_id: _product_id,
+            quantity: _quantity,
+        })
+        .collect())
 }
 
 #[derive(Queryable, Debug)]
 pub struct OrderWithFields {
     id: i32,
-    price_without_discount: f64,
-    discounted_price: f64,
+    price_without_discount: Money,
+    discounted_price: Money,
     campaign_id: Option<i32>,
     user_id: i32,
+    status: OrderStatus,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
     username: String,
     campaign_description: Option<String>,
 }
 
-async fn order_worker(
-    storage: &RedisStorage<QueryOrder>,
-    conn: &mut PgConnection,
-    order: &NewOrder,
-) -> Result<Order, DbError> {
-    let mut storage = storage.clone();
-    let created_order: Order = diesel::insert_into(orders)
-        .values(&*order)
-        .get_result(conn)?;
-    storage
-        .push(QueryOrder {
+/// Takes the items out of stock once the payment is authorized, so no product
+/// row is locked while the gateway is called. Each row is only locked by its
+/// own update, in product id order, and a product that sold out since the
+/// order was priced fails the order.
+fn reserve_stock(conn: &mut PgConnection, items: &[OrderItem]) -> Result<(), DbError> {
+    use schema::products::dsl::*;
+
+    let mut out_of_stock_ids = vec![];
+    for item in items {
+        let remaining_stock = diesel::update(products)
+            .filter(schema::products::dsl::id.eq(item.product_id))
+            .filter(stock_quantity.ge(item.quantity))
+            .set(stock_quantity.eq(stock_quantity - item.quantity))
+            .returning(stock_quantity)
+            .get_result::<i32>(conn)
+            .optional()?;
+        match remaining_stock {
+            Some(remaining_stock) => webhooks::publish_stock_change(
+                conn,
+                item.product_id,
+                remaining_stock + item.quantity,
+                remaining_stock,
+            )?,
+            None => out_of_stock_ids.push(item.product_id),
+        }
+    }
+    if !out_of_stock_ids.is_empty() {
+        return Err(ApiError::OutOfStock(out_of_stock_ids).into());
+    }
+    Ok(())
+}
+
+/// Queues the `QueryOrder` job for a new order through the outbox, in the
+/// transaction that creates the order, next to the event for the order streams.
+fn order_worker(conn: &mut PgConnection, created_order: &Order) -> Result<(), DbError> {
+    outbox::enqueue(
+        conn,
+        &QueryOrder {
             id: created_order.id,
             price_without_discount: created_order.price_without_discount,
             discounted_price: created_order.discounted_price,
             campaign_id: created_order.campaign_id,
             user_id: created_order.user_id,
-        })
-        .await?;
-    Ok(created_order)
+        },
+    )?;
+    order_events::publish(conn, order_events::ORDER_CREATED, created_order)?;
+    Ok(())
 }
 
-pub fn get_all_orders(conn: &mut PgConnection) -> Result<Vec<Value>, DbError> {
-    use rust_order_api::models::OrderToProduct;
+fn filter_orders<'a>(query: &OrderQuery) -> schema::orders::BoxedQuery<'a, Pg> {
+    let mut filtered_orders = orders.into_boxed();
+    if let Some(_user_id) = query.user_id {
+        filtered_orders = filtered_orders.filter(user_id.eq(_user_id));
+    }
+    if let Some(_campaign_id) = query.campaign_id {
+        filtered_orders = filtered_orders.filter(campaign_id.eq(_campaign_id));
+    }
+    if let Some(_status) = query.status {
+        filtered_orders = filtered_orders.filter(status.eq(_status));
+    }
+    if let Some(min_price) = query.min_price {
+        filtered_orders = filtered_orders.filter(discounted_price.ge(min_price));
+    }
+    if let Some(max_price) = query.max_price {
+        filtered_orders = filtered_orders.filter(discounted_price.le(max_price));
+    }
+    if let Some(created_from) = query.created_from {
+        filtered_orders = filtered_orders.filter(created_at.ge(created_from));
+    }
+    if let Some(created_to) = query.created_to {
+        filtered_orders = filtered_orders.filter(created_at.le(created_to));
+    }
+    filtered_orders
+}
+
+pub fn get_all_orders(conn: &mut PgConnection, query: &OrderQuery) -> Result<Value, DbError> {
     use schema::campaigns::dsl::*;
-    use schema::categories::dsl::*;
-    use schema::products::dsl::*;
     use schema::users::dsl::*;
 
-    let all_orders: HashMap<i32, Order> = orders
-        .select((schema::orders::id, Order::as_select()))
-        .load::<(i32, Order)>(conn)?
-        .into_iter()
-        .collect();
+    let page = query.page.unwrap_or(1);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE).contains(&page) {
+        return Err(
+            ApiError::BadRequest(format!("page must be between 1 and {}", MAX_PAGE)).into(),
+        );
+    }
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_LIMIT
+        ))
+        .into());
+    }
 
-    let order_values: Vec<&Order> = all_orders.values().collect();
-    let user_ids: Vec<i32> = all_orders.values().map(|order| order.user_id).collect();
+    let page_offset = (page - 1)
+        .checked_mul(limit)
+        .ok_or_else(|| ApiError::BadRequest("page is too large".to_string()))?;
 
-    let usernames: HashMap<i32, String> = users
-        .filter(schema::users::dsl::id.eq_any(user_ids))
-        .select((schema::users::id, sql::<Text>("username")))
-        .load::<(i32, String)>(conn)?
-        .into_iter()
-        .collect();
+    let total = filter_orders(query).count().get_result::<i64>(conn)?;
 
-    let order_with_fields: Vec<OrderWithFields> = orders
+    let sorted_orders = filter_orders(query);
+    let sorted_orders = match (
+        query.sort_by.unwrap_or(OrderSortField::Id),
+        query.direction.unwrap_or(SortDirection::Desc),
+    ) {
+        (OrderSortField::Id, SortDirection::Asc) => sorted_orders.order(schema::orders::id.asc()),
+        (OrderSortField::Id, SortDirection::Desc) => sorted_orders.order(schema::orders::id.desc()),
+        (OrderSortField::CreatedAt, SortDirection::Asc) => sorted_orders
+            .order(schema::orders::created_at.asc())
+            .then_order_by(schema::orders::id.asc()),
+        (OrderSortField::CreatedAt, SortDirection::Desc) => sorted_orders
+            .order(schema::orders::created_at.desc())
+            .then_order_by(schema::orders::id.desc()),
+        (OrderSortField::DiscountedPrice, SortDirection::Asc) => sorted_orders
+            .order(schema::orders::discounted_price.asc())
+            .then_order_by(schema::orders::id.asc()),
+        (OrderSortField::DiscountedPrice, SortDirection::Desc) => sorted_orders
+            .order(schema::orders::discounted_price.desc())
+            .then_order_by(schema::orders::id.desc()),
+        (OrderSortField::PriceWithoutDiscount, SortDirection::Asc) => sorted_orders
+            .order(schema::orders::price_without_discount.asc())
+            .then_order_by(schema::orders::id.asc()),
+        (OrderSortField::PriceWithoutDiscount, SortDirection::Desc) => sorted_orders
+            .order(schema::orders::price_without_discount.desc())
+            .then_order_by(schema::orders::id.desc()),
+    };
+
+    let page_orders: Vec<Order> = sorted_orders
+        .select(Order::as_select())
+        .limit(limit)
+        .offset(page_offset)
+        .load::<Order>(conn)?;
+    let page_ids: Vec<i32> = page_orders.iter().map(|order| order.id).collect();
+
+    let order_with_fields: HashMap<i32, OrderWithFields> = orders
+        .filter(schema::orders::id.eq_any(&page_ids))
         .inner_join(users.on(schema::orders::dsl::user_id.eq(schema::users::id)))
         .left_outer_join(
             campaigns.on(schema::orders::dsl::campaign_id.eq(schema::campaigns::id.nullable())),
@@ -97,87 +374,115 @@ pub fn get_all_orders(conn: &mut PgConnection) -> Result<Vec<Value>, DbError> {
             schema::orders::discounted_price,
             schema::orders::campaign_id.nullable(),
             schema::orders::user_id,
+            schema::orders::status,
+            schema::orders::created_at,
+            schema::orders::updated_at,
             schema::users::username,
             schema::campaigns::description.nullable(),
         ))
-        .load(conn)
-        .expect("Could not get orders");
+        .load::<OrderWithFields>(conn)?
+        .into_iter()
+        .map(|order| (order.id, order))
+        .collect();
 
-    let all_products: HashMap<i32, Vec<ProductWithCategory>> =
-        OrderToProduct::belonging_to(&order_values)
-            .inner_join(products.inner_join(categories))
-            .select((
-                schema::orders_products::order_id,
-                Product::as_select(),
-                schema::categories::title,
-            ))
-            .load::<(i32, Product, String)>(conn)?
-            .into_iter()
-            .fold(
-                HashMap::new(),
-                |mut acc, (order_id, product, category_title)| {
-                    acc.entry(order_id)
-                        .or_insert_with(Vec::new)
-                        .push(ProductWithCategory {
-                            product,
-                            category_title,
-                        });
-                    acc
-                },
-            );
+    let all_lines: HashMap<i32, Vec<OrderToProduct>> = OrderToProduct::belonging_to(&page_orders)
+        .select(OrderToProduct::as_select())
+        .order(schema::orders_products::product_id)
+        .load::<OrderToProduct>(conn)?
+        .into_iter()
+        .fold(HashMap::new(), |mut acc, line| {
+            acc.entry(line.order_id).or_insert_with(Vec::new).push(line);
+            acc
+        });
 
     let mut orders_json = vec![];
 
-    for order in order_with_fields {
-        let default_products: Vec<ProductWithCategory> = vec![];
-        let products_for_order = all_products.get(&order.id).unwrap_or(&default_products);
-        if let Some(_username) = usernames.get(&order.user_id) {
-            orders_json.push(json!({
-                "id": order.id,
-                "price_without_discount": order.price_without_discount,
-                "discounted_price": order.discounted_price,
-                "campaign_id": order.campaign_id,
-                "user_id": order.user_id,
-                "user": {
-                    "username": _username,
-                },
-                "campaign": match order.campaign_description {
-                    Some(campaign_description) => {
-                        json!({
-                            "description": campaign_description,
-                        })
-                    }
-                    None => json!(null),
-                },
-                "products": products_for_order.iter().map(|product| {
+    for order in page_ids
+        .iter()
+        .filter_map(|order_id| order_with_fields.get(order_id))
+    {
+        let default_lines: Vec<OrderToProduct> = vec![];
+        let lines_for_order = all_lines.get(&order.id).unwrap_or(&default_lines);
+        orders_json.push(json!({
+            "id": order.id,
+            "price_without_discount": order.price_without_discount,
+            "discounted_price": order.discounted_price,
+            "campaign_id": order.campaign_id,
+            "user_id": order.user_id,
+            "status": order.status,
+            "created_at": order.created_at,
+            "updated_at": order.updated_at,
+            "user": {
+                "username": order.username,
+            },
+            "campaign": match &order.campaign_description {
+                Some(campaign_description) => {
                     json!({
-                        "id": product.product.id,
-                        "title": product.product.title,
-                        "author": product.product.author,
-                        "list_price": product.product.list_price,
-                        "stock_quantity": product.product.stock_quantity,
-                        "category": {
-                            "title": product.category_title,
-                        },
+                        "description": campaign_description,
                     })
-                }).collect::<Vec<_>>()
-            }));
-        }
+                }
+                None => json!(null),
+            },
+            "products": lines_for_order.iter().map(get_order_line_json).collect::<Vec<_>>()
+        }));
     }
-    Ok(orders_json)
+
+    Ok(json!({
+        "orders": orders_json,
+        "page": page,
+        "limit": limit,
+        "total": total,
+    }))
+}
+
+pub fn get_order_line_json(line: &OrderToProduct) -> Value {
+    json!({
+        "id": line.product_id,
+        "title": line.title,
+        "author": line.author,
+        "list_price": line.unit_price,
+        "quantity": line.quantity,
+        "category": {
+            "title": line.category_title,
+        },
+    })
+}
+
+fn get_new_order_line_json(line: &NewOrderLine) -> Value {
+    json!({
+        "id": line.product_id,
+        "title": line.title,
+        "author": line.author,
+        "list_price": line.unit_price,
+        "quantity": line.quantity,
+        "category": {
+            "title": line.category_title,
+        },
+    })
+}
+
+pub fn get_price_breakdown(order: &Order) -> Value {
+    json!({
+        "subtotal": order.subtotal,
+        "shipping_method": order.shipping_method,
+        "shipping_fee": order.shipping_fee,
+        "price_without_discount": order.price_without_discount,
+        "discount_amount": order.discount_amount,
+        "discounted_price": order.discounted_price,
+        "campaign_id": order.campaign_id,
+        "pricing_rules": order.pricing_rules,
+    })
 }
 
 pub fn get_order_by_id(conn: &mut PgConnection, order_id: i32) -> Result<Value, DbError> {
-    use rust_order_api::models::OrderToProduct;
     use schema::campaigns::dsl::*;
-    use schema::categories::dsl::*;
-    use schema::products::dsl::*;
     use schema::users::dsl::*;
 
     let order = orders
         .filter(schema::orders::dsl::id.eq(order_id))
         .first::<Order>(conn)
-        .expect("Could not get order");
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", order_id)))?;
 
     let order_with_fields: OrderWithFields = orders
         .filter(schema::orders::dsl::id.eq(order_id))
@@ -191,16 +496,28 @@ pub fn get_order_by_id(conn: &mut PgConnection, order_id: i32) -> Result<Value,
             schema::orders::discounted_price,
             schema::orders::campaign_id.nullable(),
             schema::orders::user_id,
+            schema::orders::status,
+            schema::orders::created_at,
+            schema::orders::updated_at,
             schema::users::username,
             schema::campaigns::description.nullable(),
         ))
-        .first(conn)
-        .expect("Could not get order");
+        .first(conn)?;
 
-    let all_products = OrderToProduct::belonging_to(&order)
-        .inner_join(products.inner_join(categories))
-        .select((Product::as_select(), schema::categories::title))
-        .load::<ProductWithCategory>(conn)?;
+    let order_lines = OrderToProduct::belonging_to(&order)
+        .select(OrderToProduct::as_select())
+        .order(schema::orders_products::product_id)
+        .load::<OrderToProduct>(conn)?;
+
+    let status_history = OrderStatusHistory::belonging_to(&order)
+        .select(OrderStatusHistory::as_select())
+        .order((
+            schema::order_status_history::changed_at,
+            schema::order_status_history::id,
+        ))
+        .load::<OrderStatusHistory>(conn)?;
+
+    let payment = payments::get_payment_by_order_id(conn, order_id)?;
 
     let order_json = json!({
         "id": order_with_fields.id,
@@ -208,6 +525,24 @@ pub fn get_order_by_id(conn: &mut PgConnection, order_id: i32) -> Result<Value,
         "discounted_price": order_with_fields.discounted_price,
         "campaign_id": order_with_fields.campaign_id,
         "user_id": order_with_fields.user_id,
+        "status": order_with_fields.status,
+        "created_at": order_with_fields.created_at,
+        "updated_at": order_with_fields.updated_at,
+        "cancellation": match order.cancelled_at {
+            Some(_cancelled_at) => {
+                json!({
+                    "cancelled_by": order.cancelled_by,
+                    "reason": order.cancellation_reason,
+                    "cancelled_at": _cancelled_at,
+                })
+            }
+            None => json!(null),
+        },
+        "price_breakdown": get_price_breakdown(&order),
+        "payment": payment,
+        "risk_flags": order.risk_flags,
+        "shipping_address": order.shipping_address,
+        "billing_address": order.billing_address,
         "user": {
             "username": order_with_fields.username,
         },
@@ -219,16 +554,12 @@ pub fn get_order_by_id(conn: &mut PgConnection, order_id: i32) -> Result<Value,
             }
             None => json!(null),
         },
-        "products": all_products.iter().map(|product| {
+        "products": order_lines.iter().map(get_order_line_json).collect::<Vec<_>>(),
+        "status_history": status_history.iter().map(|history| {
             json!({
-                "id": product.product.id,
-                "title": product.product.title,
-                "author": product.product.author,
-                "list_price": product.product.list_price,
-                "stock_quantity": product.product.stock_quantity,
-                "category": {
-                    "title": product.category_title,
-                },
+                "from_status": history.from_status,
+                "to_status": history.to_status,
+                "changed_at": history.changed_at,
             })
         }).collect::<Vec<_>>(),
     });
@@ -236,184 +567,354 @@ pub fn get_order_by_id(conn: &mut PgConnection, order_id: i32) -> Result<Value,
     Ok(order_json)
 }
 
-pub async fn insert_new_order(
-    mut conn: PooledConnection<r2d2::ConnectionManager<PgConnection>>,
-    mut redis_conn: PooledConnection<RedisConnectionManager>,
-    storage: web::Data<RedisStorage<QueryOrder>>,
-    _user_id: i32,
-    _product_ids: Vec<i32>,
+pub fn transition_order_status(
+    conn: &mut PgConnection,
+    _order_id: i32,
+    next_status: OrderStatus,
+) -> Result<Order, DbError> {
+    use schema::order_status_history::dsl::*;
+
+    let order = orders
+        .filter(schema::orders::dsl::id.eq(_order_id))
+        .for_update()
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", _order_id)))?;
+
+    if !order.status.can_transition_to(next_status) {
+        return Err(ApiError::InvalidStatusTransition {
+            from: order.status,
+            to: next_status,
+        }
+        .into());
+    }
+
+    let updated_order = diesel::update(orders)
+        .filter(schema::orders::dsl::id.eq(_order_id))
+        .set(schema::orders::dsl::status.eq(next_status))
+        .get_result::<Order>(conn)?;
+
+    diesel::insert_into(order_status_history)
+        .values((
+            order_id.eq(_order_id),
+            from_status.eq(Some(order.status)),
+            to_status.eq(next_status),
+        ))
+        .execute(conn)?;
+    order_events::publish(conn, order_events::ORDER_UPDATED, &updated_order)?;
+
+    Ok(updated_order)
+}
+
+/// Paying an order captures its payment, refunding it gives back what is left
+/// of the captured amount.
+pub fn update_order_status_by_id(
+    conn: &mut PgConnection,
+    gateway: &dyn PaymentGateway,
+    order_id: i32,
+    next_status: OrderStatus,
+) -> Result<Value, DbError> {
+    if next_status == OrderStatus::Cancelled {
+        return cancel_order_by_id(conn, gateway, order_id, None, None);
+    }
+    conn.transaction::<_, DbError, _>(|conn| {
+        transition_order_status(conn, order_id, next_status)?;
+        let gateway_call = match next_status {
+            OrderStatus::Paid => payments::capture_order_payment(conn, order_id)?,
+            OrderStatus::Refunded => payments::refund_order_payment(conn, order_id, None)?,
+            _ => None,
+        };
+        let order_json = get_order_by_id(conn, order_id)?;
+        payments::run_gateway_call(gateway, gateway_call)?;
+        Ok(order_json)
+    })
+}
+
+/// Creates the order, authorizes its payment and reserves the stock. Orders
+/// that exceed the risk limits are flagged and held for review by the worker
+/// instead of being rejected. A declined payment rolls the whole order back;
+/// an authorization whose order could not be saved is voided.
+pub fn insert_new_order(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    gateway: &dyn PaymentGateway,
+    order: OrderDto,
 ) -> Result<Value, DbError> {
-    let shipping_cost = 35.0;
-    use rust_order_api::models::Campaign;
+    insert_new_order_with(conn, redis_conn, gateway, order, |_, _| Ok(()))
+}
+
+/// Like `insert_new_order`, with `on_created` run on the created order before
+/// the transaction commits, so its changes are saved together with the order.
+pub fn insert_new_order_with<F>(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    gateway: &dyn PaymentGateway,
+    order: OrderDto,
+    on_created: F,
+) -> Result<Value, DbError>
+where
+    F: FnOnce(&mut PgConnection, &Value) -> Result<(), DbError>,
+{
     use rust_order_api::models::User;
     use schema::campaigns::dsl::*;
-    use schema::categories::dsl::*;
-    use schema::orders_products::dsl::*;
     use schema::products::dsl::*;
     use schema::users::dsl::*;
 
-    users
-        .filter(schema::users::dsl::id.eq(_user_id))
-        .first::<User>(&mut conn)
-        .expect("Users could not get");
+    let _user_id = order.user_id;
+    let _items = merge_order_items(&order.line_items())?;
+    let _product_ids: Vec<i32> = _items.iter().map(|item| item.product_id).collect();
+    let risk_limits = RiskLimits::from_env();
+    let mut risk_counted = false;
+    let mut authorization = None;
 
-    diesel::update(products)
-        .filter(schema::products::dsl::id.eq_any(&_product_ids))
-        .set(stock_quantity.eq(stock_quantity - 1))
-        .execute(&mut conn)
-        .expect("Products could not update");
+    let inserted_order = conn.transaction::<_, DbError, _>(|conn| {
+        users
+            .filter(schema::users::dsl::id.eq(_user_id))
+            .select(User::as_select())
+            .first::<User>(conn)
+            .optional()?
+            .ok_or_else(|| ApiError::NotFound(format!("User {} not found", _user_id)))?;
 
-    let mut order_products = products
-        .filter(schema::products::dsl::id.eq_any(&_product_ids))
-        .inner_join(categories)
-        .select((Product::as_select(), schema::categories::title))
-        .load::<ProductWithCategory>(&mut conn)
-        .expect("Products could not get");
+        let order_shipping_method =
+            get_active_shipping_method(conn, order.shipping_method.as_deref())?;
+        let order_shipping_address = get_address_by_id(conn, _user_id, order.shipping_address_id)?;
+        let order_billing_address = get_address_by_id(
+            conn,
+            _user_id,
+            order
+                .billing_address_id
+                .unwrap_or(order.shipping_address_id),
+        )?;
 
-    let all_campaigns;
+        let available_stock: HashMap<i32, i32> = products
+            .filter(schema::products::dsl::id.eq_any(&_product_ids))
+            .select((schema::products::dsl::id, stock_quantity))
+            .load::<(i32, i32)>(conn)?
+            .into_iter()
+            .collect();
 
-    let campaigns_result: Option<String> = redis::cmd("GET")
-        .arg("campaigns")
-        .query(&mut *redis_conn)
-        .unwrap();
+        let missing_ids: Vec<i32> = _product_ids
+            .iter()
+            .filter(|_product_id| !available_stock.contains_key(_product_id))
+            .copied()
+            .collect();
+        if !missing_ids.is_empty() {
+            return Err(
+                ApiError::NotFound(format!("Products not found: {:?}", missing_ids)).into(),
+            );
+        }
 
-    match campaigns_result {
-        Some(data) => match serde_json::from_str::<Vec<Campaign>>(&data) {
-            Ok(_campaigns) => all_campaigns = _campaigns,
-            Err(_) => {
-                all_campaigns = Vec::new();
-            }
-        },
-        None => {
-            all_campaigns = campaigns
-                .select(Campaign::as_select())
-                .load(&mut conn)
-                .expect("Campaigns could not get");
-            let _: () = redis::cmd("SET")
-                .arg("campaigns")
-                .arg(serde_json::to_string(&all_campaigns).unwrap())
-                .arg("EX")
-                .arg(30)
-                .query(&mut *redis_conn)
-                .expect("Cache could not set (redis)");
+        let out_of_stock_ids: Vec<i32> = _items
+            .iter()
+            .filter(|item| available_stock[&item.product_id] < item.quantity)
+            .map(|item| item.product_id)
+            .collect();
+        if !out_of_stock_ids.is_empty() {
+            return Err(ApiError::OutOfStock(out_of_stock_ids).into());
         }
-    }
 
-    let mut total_price: f64 = order_products
-        .iter()
-        .map(|product| product.product.list_price)
-        .sum();
+        let order_products = load_order_products(conn, &_items)?;
 
-    if total_price < 150.0 {
-        total_price += shipping_cost;
-    }
+        let all_campaigns = get_cached_campaigns(conn, redis_conn)?;
 
-    let available_campaigns =
-        functions::get_available_campaigns(all_campaigns, &mut order_products);
+        let pricing =
+            functions::get_order_pricing(all_campaigns, &order_products, order_shipping_method)?;
 
-    #[derive(Debug)]
-    struct DiscountedPrices {
-        campaign_id: Option<i32>,
-        discounted_price: f64,
-    }
-    let mut discounted_prices: Vec<DiscountedPrices> = vec![];
-
-    for campaign in available_campaigns {
-        let _discounted_price =
-            functions::get_discounted_total_price(&campaign, &mut order_products, total_price);
-        discounted_prices.push(DiscountedPrices {
-            campaign_id: Some(campaign.id),
-            discounted_price: _discounted_price,
-        });
-    }
+        let _risk_flags = risk::check_order(
+            redis_conn,
+            &risk_limits,
+            _user_id,
+            true,
+            &_items,
+            Some(pricing.discounted_price),
+        )?;
+        risk_counted = true;
 
-    let min_discounted_campaign = discounted_prices
-        .iter()
-        .min_by(|a, b| a.discounted_price.partial_cmp(&b.discounted_price).unwrap());
+        let new_order = NewOrder {
+            price_without_discount: pricing.price_without_discount,
+            discounted_price: pricing.discounted_price,
+            campaign_id: pricing.campaign_id,
+            user_id: _user_id.to_owned(),
+            subtotal: pricing.subtotal,
+            shipping_fee: pricing.shipping_cost,
+            discount_amount: pricing.discount_amount(),
+            pricing_rules: pricing.pricing_rules(),
+            shipping_method: pricing.shipping_method.code.clone(),
+            shipping_address: Some(get_address_snapshot(&order_shipping_address)),
+            billing_address: Some(get_address_snapshot(&order_billing_address)),
+            status: OrderStatus::Processing,
+            risk_flags: (!_risk_flags.is_empty()).then(|| json!(_risk_flags)),
+        };
+        let created_order: Order = diesel::insert_into(orders)
+            .values(&new_order)
+            .get_result(conn)?;
 
-    let (_discounted_price, _campaign_id) = match min_discounted_campaign {
-        Some(min_campaign) => (min_campaign.discounted_price, min_campaign.campaign_id),
-        None => (total_price, None),
-    };
+        diesel::insert_into(schema::order_status_history::table)
+            .values((
+                schema::order_status_history::order_id.eq(created_order.id),
+                schema::order_status_history::to_status.eq(created_order.status),
+            ))
+            .execute(conn)?;
 
-    let new_order = NewOrder {
-        price_without_discount: (total_price * 1000.0).round() / 1000.0,
-        discounted_price: (_discounted_price * 1000.0).round() / 1000.0,
-        campaign_id: _campaign_id,
-        user_id: _user_id.to_owned(),
-    };
-    let order_queue = order_worker(&storage, &mut conn, &new_order);
-    let created_order = order_queue.await?;
+        let order_lines: Vec<NewOrderLine> = order_products
+            .iter()
+            .map(|product| NewOrderLine {
+                order_id: created_order.id,
+                product_id: product.product.id,
+                quantity: product.quantity,
+                unit_price: product.product.list_price,
+                title: product.product.title.clone(),
+                author: product.product.author.clone(),
+                category_title: product.category_title.clone(),
+            })
+            .collect();
+        diesel::insert_into(schema::orders_products::table)
+            .values(&order_lines)
+            .execute(conn)?;
 
-    let order_with_fields: OrderWithFields = orders
-        .filter(schema::orders::dsl::id.eq(created_order.id))
-        .inner_join(users.on(schema::orders::dsl::user_id.eq(schema::users::id)))
-        .left_outer_join(
-            campaigns.on(schema::orders::dsl::campaign_id.eq(schema::campaigns::id.nullable())),
-        )
-        .select((
-            schema::orders::id,
-            schema::orders::price_without_discount,
-            schema::orders::discounted_price,
-            schema::orders::campaign_id.nullable(),
-            schema::orders::user_id,
-            schema::users::username,
-            schema::campaigns::description.nullable(),
-        ))
-        .first(&mut conn)?;
+        let payment = payments::authorize_order_payment(
+            conn,
+            gateway,
+            &created_order,
+            order.payment_method.clone(),
+            &mut authorization,
+        )?;
+        reserve_stock(conn, &_items)?;
 
-    let order_json = json!({
-        "id": order_with_fields.id,
-        "price_without_discount": order_with_fields.price_without_discount,
-        "discounted_price": order_with_fields.discounted_price,
-        "campaign_id": order_with_fields.campaign_id,
-        "user_id": order_with_fields.user_id,
-        "user": {
-            "username": order_with_fields.username,
-        },
-        "campaign": match order_with_fields.campaign_description {
-            Some(campaign_description) => {
-                json!({
-                    "description": campaign_description,
-                })
-            }
-            None => json!(null),
-        },
-        "products": order_products.iter().map(|product| {
-            json!({
-                "id": product.product.id,
-                "title": product.product.title,
-                "author": product.product.author,
-                "list_price": product.product.list_price,
-                "stock_quantity": product.product.stock_quantity,
-                "category": {
-                    "title": product.category_title,
-                },
-            })
-        }).collect::<Vec<_>>(),
+        let order_with_fields: OrderWithFields = orders
+            .filter(schema::orders::dsl::id.eq(created_order.id))
+            .inner_join(users.on(schema::orders::dsl::user_id.eq(schema::users::id)))
+            .left_outer_join(
+                campaigns.on(schema::orders::dsl::campaign_id.eq(schema::campaigns::id.nullable())),
+            )
+            .select((
+                schema::orders::id,
+                schema::orders::price_without_discount,
+                schema::orders::discounted_price,
+                schema::orders::campaign_id.nullable(),
+                schema::orders::user_id,
+                schema::orders::status,
+                schema::orders::created_at,
+                schema::orders::updated_at,
+                schema::users::username,
+                schema::campaigns::description.nullable(),
+            ))
+            .first(conn)?;
+
+        let order_json = json!({
+            "id": order_with_fields.id,
+            "price_without_discount": order_with_fields.price_without_discount,
+            "discounted_price": order_with_fields.discounted_price,
+            "campaign_id": order_with_fields.campaign_id,
+            "user_id": order_with_fields.user_id,
+            "status": order_with_fields.status,
+            "created_at": order_with_fields.created_at,
+            "updated_at": order_with_fields.updated_at,
+            "price_breakdown": get_price_breakdown(&created_order),
+            "payment": payment,
+            "risk_flags": created_order.risk_flags,
+            "shipping_address": created_order.shipping_address,
+            "billing_address": created_order.billing_address,
+            "user": {
+                "username": order_with_fields.username,
+            },
+            "campaign": match order_with_fields.campaign_description {
+                Some(campaign_description) => {
+                    json!({
+                        "description": campaign_description,
+                    })
+                }
+                None => json!(null),
+            },
+            "products": order_lines.iter().map(get_new_order_line_json).collect::<Vec<_>>(),
+        });
+
+        order_worker(conn, &created_order)?;
+        webhooks::publish_event(conn, webhooks::ORDER_CREATED, &order_json)?;
+        on_created(conn, &order_json)?;
+        Ok(order_json)
     });
 
-    for _product_id in _product_ids {
-        diesel::insert_into(orders_products)
-            .values((order_id.eq(&created_order.id), product_id.eq(_product_id)))
-            .execute(&mut conn)?;
+    if inserted_order.is_err() {
+        if risk_counted {
+            if let Err(err) = risk::release_order(redis_conn, &risk_limits, _user_id, true, &_items)
+            {
+                tracing::error!(
+                    "Could not release risk counters of user {}: {}",
+                    _user_id,
+                    err
+                );
+            }
+        }
+        if let Some(reference) = authorization {
+            payments::void_after_rollback(conn, &reference);
+        }
     }
-    Ok(order_json)
+    inserted_order
 }
 
-pub fn delete_order_by_id(conn: &mut PgConnection, order_id: i32) -> Result<String, DbError> {
-    diesel::delete(orders.filter(id.eq(order_id))).execute(conn)?;
-    Ok("Order deleted".to_string())
+pub fn cancel_order_by_id(
+    conn: &mut PgConnection,
+    gateway: &dyn PaymentGateway,
+    _order_id: i32,
+    _cancelled_by: Option<String>,
+    _cancellation_reason: Option<String>,
+) -> Result<Value, DbError> {
+    use schema::products::dsl::*;
+
+    conn.transaction::<_, DbError, _>(|conn| {
+        let previous_status = orders
+            .filter(schema::orders::dsl::id.eq(_order_id))
+            .select(schema::orders::dsl::status)
+            .for_update()
+            .first::<OrderStatus>(conn)
+            .optional()?;
+        let cancelled_order = transition_order_status(conn, _order_id, OrderStatus::Cancelled)?;
+        // The worker only counts orders once they leave processing.
+        if previous_status != Some(OrderStatus::Processing) {
+            fulfilment::remove_user_stats(conn, &cancelled_order)?;
+        }
+
+        let order_lines = OrderToProduct::belonging_to(&cancelled_order)
+            .select(OrderToProduct::as_select())
+            .order(schema::orders_products::product_id)
+            .load::<OrderToProduct>(conn)?;
+
+        for line in &order_lines {
+            diesel::update(products)
+                .filter(schema::products::dsl::id.eq(line.product_id))
+                .set(stock_quantity.eq(stock_quantity + line.quantity))
+                .execute(conn)?;
+        }
+
+        diesel::update(orders)
+            .filter(schema::orders::dsl::id.eq(_order_id))
+            .set((
+                cancelled_by.eq(_cancelled_by),
+                cancellation_reason.eq(_cancellation_reason),
+                cancelled_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)?;
+        let gateway_call = payments::release_order_payment(conn, _order_id)?;
+
+        let order_json = get_order_by_id(conn, _order_id)?;
+        webhooks::publish_event(conn, webhooks::ORDER_CANCELLED, &order_json)?;
+        payments::run_gateway_call(gateway, gateway_call)?;
+        Ok(order_json)
+    })
 }
 
 #[get("/api/orders")]
-async fn get_orders(pool: web::Data<DbPool>) -> Result<impl Responder> {
+async fn get_orders(
+    pool: web::Data<DbPool>,
+    query: web::Query<OrderQuery>,
+) -> Result<impl Responder> {
     let all_orders = web::block(move || {
         let mut conn = pool.get()?;
-        get_all_orders(&mut conn)
+        get_all_orders(&mut conn, &query)
     })
     .await?
-    .map_err(error::ErrorInternalServerError)?;
+    .map_err(errors::into_response_error)?;
     Ok(HttpResponse::Ok().json(all_orders))
 }
 
@@ -424,36 +925,216 @@ async fn get_order(pool: web::Data<DbPool>, order_id: web::Path<i32>) -> Result<
         get_order_by_id(&mut conn, *order_id)
     })
     .await?
-    .map_err(error::ErrorInternalServerError)?;
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order))
+}
+
+#[patch("/api/orders/{order_id}/status")]
+async fn update_order_status(
+    pool: web::Data<DbPool>,
+    gateway: web::Data<dyn PaymentGateway>,
+    order_id: web::Path<i32>,
+    form: web::Json<OrderStatusDto>,
+) -> Result<impl Responder> {
+    let order = web::block(move || {
+        let mut conn = pool.get()?;
+        update_order_status_by_id(&mut conn, &**gateway, *order_id, form.status)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
     Ok(HttpResponse::Ok().json(order))
 }
 
 #[post("/api/orders")]
 async fn create_order(
+    req: HttpRequest,
     db_pool: web::Data<DbPool>,
     redis_pool: web::Data<Pool<RedisConnectionManager>>,
-    storage: web::Data<RedisStorage<QueryOrder>>,
+    gateway: web::Data<dyn PaymentGateway>,
+    query: web::Query<CreateOrderQuery>,
     form: web::Json<OrderDto>,
+) -> Result<impl Responder> {
+    let is_async = query.is_async;
+    let _user_id = form.user_id;
+    let idempotency_key = idempotency::get_idempotency_key(&req);
+    let fingerprint = idempotency::get_fingerprint(&(
+        form.user_id,
+        form.line_items(),
+        &form.shipping_method,
+        form.shipping_address_id,
+        form.billing_address_id,
+        &form.payment_method,
+        is_async,
+    ))
+    .map_err(error::ErrorInternalServerError)?;
+
+    if let Some(key) = idempotency_key.clone() {
+        let db_pool = db_pool.clone();
+        let redis_pool = redis_pool.clone();
+        let fingerprint = fingerprint.clone();
+        let state = web::block(move || {
+            let mut db_conn = db_pool.get()?;
+            let mut redis_conn = redis_pool.get()?;
+            idempotency::begin_request(&mut db_conn, &mut redis_conn, _user_id, &key, &fingerprint)
+        })
+        .await?
+        .map_err(errors::into_response_error)?;
+        if let IdempotencyState::Completed(order) = state {
+            return Ok(get_create_order_response(is_async, &order)
+                .insert_header(("Idempotent-Replayed", "true"))
+                .json(order));
+        }
+    }
+
+    // The response is saved with the order, so a retry after a lost response
+    // replays it instead of placing the order again.
+    let renewal = idempotency_key.clone().map(|key| {
+        rt::spawn(idempotency::keep_reserved(
+            redis_pool.get_ref().clone(),
+            _user_id,
+            key,
+        ))
+    });
+    let redis_order_pool = redis_pool.clone();
+    let stored_key = idempotency_key.clone();
+    let order = web::block(move || {
+        let complete_request = |conn: &mut PgConnection, order: &Value| match &stored_key {
+            Some(key) => idempotency::complete_request(conn, _user_id, key, &fingerprint, order),
+            None => Ok(()),
+        };
+        let mut db_conn = db_pool.get()?;
+        if is_async {
+            return order_jobs::submit_order(&mut db_conn, form.into_inner(), complete_request)
+                .map(|order_job| json!(order_job));
+        }
+        let mut redis_conn: PooledConnection<RedisConnectionManager> = redis_order_pool.get()?;
+        insert_new_order_with(
+            &mut db_conn,
+            &mut redis_conn,
+            &**gateway,
+            form.into_inner(),
+            complete_request,
+        )
+    })
+    .await;
+    if let Some(renewal) = renewal {
+        renewal.abort();
+    }
+
+    if let Some(key) = idempotency_key {
+        let released = web::block(move || {
+            let mut redis_conn = redis_pool.get()?;
+            idempotency::release_request(&mut redis_conn, _user_id, &key)
+        })
+        .await;
+        if let Err(err) = released
+            .map_err(DbError::from)
+            .and_then(|released| released)
+        {
+            tracing::warn!(
+                "Could not release idempotency key of user {}: {}",
+                _user_id,
+                err
+            );
+        }
+    }
+
+    let order = order?.map_err(errors::into_response_error)?;
+    Ok(get_create_order_response(is_async, &order).json(order))
+}
+
+/// Synchronous requests respond 201 with the order. Asynchronous ones respond
+/// 202 with the queued order job and point to where it can be polled.
+fn get_create_order_response(is_async: bool, order: &Value) -> HttpResponseBuilder {
+    if is_async {
+        let mut response = HttpResponse::Accepted();
+        response.insert_header(("Location", format!("/api/order_jobs/{}", order["id"])));
+        response
+    } else {
+        HttpResponse::Created()
+    }
+}
+
+#[post("/api/orders/{order_id}/cancel")]
+async fn cancel_order(
+    pool: web::Data<DbPool>,
+    gateway: web::Data<dyn PaymentGateway>,
+    order_id: web::Path<i32>,
+    form: web::Json<CancelOrderDto>,
 ) -> Result<impl Responder> {
     let order = web::block(move || {
-        let db_conn = db_pool.get().expect("DB pool could not get");
-        let redis_conn: PooledConnection<RedisConnectionManager> = redis_pool.get().expect("Redis pool could not get");
-        let product_ids = form.product_ids.clone();
-        insert_new_order(db_conn, redis_conn, storage, form.user_id, product_ids)
+        let mut conn = pool.get()?;
+        let form = form.into_inner();
+        cancel_order_by_id(
+            &mut conn,
+            &**gateway,
+            *order_id,
+            form.cancelled_by,
+            form.reason,
+        )
     })
     .await?
-    .map_err(error::ErrorInternalServerError)
-    .await?;
-    Ok(HttpResponse::Created().json(order))
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order))
 }
 
 #[delete("/api/orders/{order_id}")]
-async fn delete_order(pool: web::Data<DbPool>, order_id: web::Path<i32>) -> Result<impl Responder> {
+async fn delete_order(
+    pool: web::Data<DbPool>,
+    gateway: web::Data<dyn PaymentGateway>,
+    order_id: web::Path<i32>,
+) -> Result<impl Responder> {
     let order = web::block(move || {
         let mut conn = pool.get()?;
-        delete_order_by_id(&mut conn, *order_id)
+        cancel_order_by_id(&mut conn, &**gateway, *order_id, None, None)
     })
     .await?
-    .map_err(error::ErrorInternalServerError)?;
+    .map_err(errors::into_response_error)?;
     Ok(HttpResponse::Ok().json(order))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(_product_id: i32, _quantity: i32) -> OrderItem {
+        OrderItem {
+            product_id: _product_id,
+            quantity: _quantity,
+        }
+    }
+
+    #[test]
+    fn merge_order_items_sums_quantities_per_product() {
+        let merged = merge_order_items(&[item(3, 1), item(1, 2), item(3, 4)]).unwrap();
+        assert_eq!(merged, vec![item(1, 2), item(3, 5)]);
+    }
+
+    #[test]
+    fn merge_order_items_rejects_empty_orders() {
+        assert!(matches!(
+            merge_order_items(&[]),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn merge_order_items_rejects_quantities_below_one() {
+        assert!(matches!(
+            merge_order_items(&[item(1, 2), item(2, 0)]),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            merge_order_items(&[item(1, -1)]),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn merge_order_items_rejects_quantities_that_overflow() {
+        assert!(matches!(
+            merge_order_items(&[item(1, i32::MAX), item(1, 1)]),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::controllers::order_events::{OrderEvent, OrderEventHub};
+use crate::controllers::order_jobs;
+use crate::controllers::payments::{PaymentGateway, VoidAuthorization};
+use crate::insertables::NewOutboxMessage;
+use crate::{DeliverWebhook, QueryOrder, SubmitOrder};
+use actix_web::web;
+use apalis::prelude::*;
+use apalis::redis::RedisStorage;
+use diesel::dsl::{now, IntervalDsl};
+use diesel::{prelude::*, r2d2};
+use rust_order_api::models::OutboxMessage;
+use rust_order_api::schema::outbox_messages::dsl::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+const DEFAULT_BATCH_SIZE: i64 = 100;
+const DEFAULT_MAX_ATTEMPTS: i32 = 10;
+const DEFAULT_RETENTION_HOURS: i32 = 24;
+const LEASE_SECONDS: i32 = 30;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Records a job in the outbox, to be pushed to its apalis storage by the
+/// relay once the surrounding transaction commits.
+pub fn enqueue<J: Job + Serialize>(
+    conn: &mut PgConnection,
+    job: &J,
+) -> Result<OutboxMessage, DbError> {
+    enqueue_payload(conn, J::NAME, serde_json::to_value(job)?)
+}
+
+/// Records an already serialized job, such as one taken back from the
+/// dead-letter store.
+pub fn enqueue_payload(
+    conn: &mut PgConnection,
+    _job_name: &str,
+    _payload: Value,
+) -> Result<OutboxMessage, DbError> {
+    let new_outbox_message = NewOutboxMessage {
+        job_name: _job_name.to_string(),
+        payload: _payload,
+    };
+    let outbox_message = diesel::insert_into(outbox_messages)
+        .values(&new_outbox_message)
+        .returning(OutboxMessage::as_returning())
+        .get_result(conn)?;
+    Ok(outbox_message)
+}
+
+/// Leases a batch of undelivered messages, oldest first. Rows leased by
+/// another relay are skipped; a lease that runs out without the message being
+/// delivered makes it available again.
+fn lease_outbox_messages(
+    conn: &mut PgConnection,
+    batch_size: i64,
+) -> Result<Vec<OutboxMessage>, DbError> {
+    conn.transaction::<_, DbError, _>(|conn| {
+        let message_ids: Vec<i32> = outbox_messages
+            .filter(delivered_at.is_null())
+            .filter(locked_until.is_null().or(locked_until.lt(now)))
+            .order(id)
+            .limit(batch_size)
+            .select(id)
+            .for_update()
+            .skip_locked()
+            .load(conn)?;
+        let mut leased_messages = diesel::update(outbox_messages)
+            .filter(id.eq_any(&message_ids))
+            .set(locked_until.eq((now + LEASE_SECONDS.seconds()).nullable()))
+            .returning(OutboxMessage::as_returning())
+            .get_results::<OutboxMessage>(conn)?;
+        leased_messages.sort_by_key(|message| message.id);
+        Ok(leased_messages)
+    })
+}
+
+fn mark_delivered(conn: &mut PgConnection, outbox_message_id: i32) -> Result<(), DbError> {
+    diesel::update(outbox_messages)
+        .filter(id.eq(outbox_message_id))
+        .set((
+            delivered_at.eq(now),
+            locked_until.eq(None::<chrono::NaiveDateTime>),
+            attempts.eq(attempts + 1),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Keeps the lease, so the message is tried again once it runs out. A message
+/// that failed `max_attempts` times is moved to the dead-letter store instead.
+fn mark_failed(
+    conn: &mut PgConnection,
+    message: &OutboxMessage,
+    error: &str,
+    max_attempts: i32,
+) -> Result<(), DbError> {
+    let _attempts = message.attempts + 1;
+    if _attempts < max_attempts {
+        diesel::update(outbox_messages)
+            .filter(id.eq(message.id))
+            .set((attempts.eq(_attempts), last_error.eq(error)))
+            .execute(conn)?;
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Outbox message {} ({}) failed {} times and was dead-lettered: {}",
+        message.id,
+        message.job_name,
+        _attempts,
+        error
+    );
+    conn.transaction::<_, DbError, _>(|conn| {
+        diesel::delete(outbox_messages.filter(id.eq(message.id))).execute(conn)?;
+        order_jobs::insert_dead_letter_job(
+            conn,
+            &message.job_name,
+            &format!("outbox:{}", message.id),
+            message.payload.clone(),
+            error,
+            _attempts,
+        )?;
+        Ok(())
+    })
+}
+
+/// Deletes messages delivered more than `retention_hours` ago.
+fn prune_delivered(conn: &mut PgConnection, retention_hours: i32) -> Result<usize, DbError> {
+    let pruned = diesel::delete(
+        outbox_messages.filter(delivered_at.lt((now - retention_hours.hours()).nullable())),
+    )
+    .execute(conn)?;
+    Ok(pruned)
+}
+
+async fn push_job<J>(storage: &RedisStorage<J>, job_payload: Value) -> Result<(), DbError>
+where
+    J: Job + Serialize + DeserializeOwned + Send + Sync + Unpin + 'static,
+{
+    let job = serde_json::from_value::<J>(job_payload)?;
+    let mut storage = storage.clone();
+    storage.push(job).await?;
+    Ok(())
+}
+
+/// Moves outbox messages to the apalis storages, order events to the streams
+/// open on this process and voids to the payment gateway. Delivery is at
+/// least once: a relay that stops between pushing a job and marking it
+/// delivered pushes it again, so every job consumer skips work it already did.
+#[derive(Clone)]
+pub struct OutboxRelay {
+    pool: DbPool,
+    order_storage: RedisStorage<QueryOrder>,
+    submit_order_storage: RedisStorage<SubmitOrder>,
+    webhook_storage: RedisStorage<DeliverWebhook>,
+    order_events: OrderEventHub,
+    gateway: Arc<dyn PaymentGateway>,
+    poll_interval: Duration,
+    batch_size: i64,
+    max_attempts: i32,
+    retention_hours: i32,
+}
+
+impl OutboxRelay {
+    /// Reads OUTBOX_POLL_INTERVAL_MS, OUTBOX_BATCH_SIZE, OUTBOX_MAX_ATTEMPTS
+    /// and OUTBOX_RETENTION_HOURS, falling back to 500ms, 100 messages, 10
+    /// attempts and a day.
+    pub fn new(
+        pool: DbPool,
+        order_storage: RedisStorage<QueryOrder>,
+        submit_order_storage: RedisStorage<SubmitOrder>,
+        webhook_storage: RedisStorage<DeliverWebhook>,
+        order_events: OrderEventHub,
+        gateway: Arc<dyn PaymentGateway>,
+    ) -> Self {
+        fn read<T: std::str::FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+        OutboxRelay {
+            pool,
+            order_storage,
+            submit_order_storage,
+            webhook_storage,
+            order_events,
+            gateway,
+            poll_interval: Duration::from_millis(read(
+                "OUTBOX_POLL_INTERVAL_MS",
+                DEFAULT_POLL_INTERVAL_MS,
+            )),
+            batch_size: read("OUTBOX_BATCH_SIZE", DEFAULT_BATCH_SIZE).max(1),
+            max_attempts: read("OUTBOX_MAX_ATTEMPTS", DEFAULT_MAX_ATTEMPTS).max(1),
+            retention_hours: read("OUTBOX_RETENTION_HOURS", DEFAULT_RETENTION_HOURS).max(0),
+        }
+    }
+
+    async fn push(&self, message: &OutboxMessage) -> Result<(), DbError> {
+        match message.job_name.as_str() {
+            QueryOrder::NAME => push_job(&self.order_storage, message.payload.clone()).await,
+            SubmitOrder::NAME => {
+                push_job(&self.submit_order_storage, message.payload.clone()).await
+            }
+            DeliverWebhook::NAME => push_job(&self.webhook_storage, message.payload.clone()).await,
+            OrderEvent::NAME => {
+                let event = serde_json::from_value::<OrderEvent>(message.payload.clone())?;
+                self.order_events.send(message.id, event);
+                Ok(())
+            }
+            VoidAuthorization::NAME => {
+                let job = serde_json::from_value::<VoidAuthorization>(message.payload.clone())?;
+                let gateway = self.gateway.clone();
+                web::block(move || gateway.void(&job.reference)).await??;
+                Ok(())
+            }
+            _ => Err(format!("Unknown job {}", message.job_name).into()),
+        }
+    }
+
+    /// Relays one batch and returns how many messages were delivered.
+    pub async fn relay_batch(&self) -> Result<usize, DbError> {
+        let pool = self.pool.clone();
+        let batch_size = self.batch_size;
+        let leased_messages = web::block(move || {
+            let mut conn = pool.get()?;
+            lease_outbox_messages(&mut conn, batch_size)
+        })
+        .await??;
+
+        let mut delivered = 0;
+        for message in leased_messages {
+            let pushed = self.push(&message).await;
+            let pool = self.pool.clone();
+            let max_attempts = self.max_attempts;
+            let is_delivered = pushed.is_ok();
+            web::block(move || {
+                let mut conn = pool.get()?;
+                match pushed {
+                    Ok(()) => mark_delivered(&mut conn, message.id),
+                    Err(err) => mark_failed(&mut conn, &message, &err.to_string(), max_attempts),
+                }
+            })
+            .await??;
+            if is_delivered {
+                delivered += 1;
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Deletes old delivered messages, so the table only keeps what is still
+    /// on its way and a short history.
+    pub async fn prune(&self) -> Result<usize, DbError> {
+        let pool = self.pool.clone();
+        let retention_hours = self.retention_hours;
+        web::block(move || {
+            let mut conn = pool.get()?;
+            prune_delivered(&mut conn, retention_hours)
+        })
+        .await?
+    }
+
+    /// Relays until the process stops, waiting `poll_interval` whenever the
+    /// outbox is empty or the database cannot be reached. Delivered messages
+    /// are pruned about once a minute.
+    pub async fn run(self) -> std::io::Result<()> {
+        let mut last_pruned: Option<Instant> = None;
+        loop {
+            if last_pruned.is_none_or(|pruned_at| pruned_at.elapsed() >= PRUNE_INTERVAL) {
+                last_pruned = Some(Instant::now());
+                if let Err(err) = self.prune().await {
+                    tracing::error!("Outbox prune error: {}", err);
+                }
+            }
+            match self.relay_batch().await {
+                Ok(delivered) if delivered > 0 => continue,
+                Ok(_) => {}
+                Err(err) => tracing::error!("Outbox relay error: {}", err),
+            }
+            actix_web::rt::time::sleep(self.poll_interval).await;
+        }
+    }
+}
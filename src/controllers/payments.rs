@@ -0,0 +1,359 @@
+use crate::controllers::errors::ApiError;
+use crate::controllers::outbox;
+use crate::insertables::NewPayment;
+use apalis::prelude::Job;
+use diesel::prelude::*;
+use rust_order_api::models::{Money, Order, Payment, PaymentStatus};
+use rust_order_api::schema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum PaymentError {
+    /// The payment method was refused, with the gateway's reason.
+    Declined(String),
+    /// The gateway could not be reached or failed to answer.
+    Unavailable(String),
+}
+
+impl fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentError::Declined(reason) => write!(f, "Payment declined: {}", reason),
+            PaymentError::Unavailable(message) => write!(f, "Payment gateway error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PaymentError {}
+
+impl From<PaymentError> for ApiError {
+    fn from(err: PaymentError) -> Self {
+        match err {
+            PaymentError::Declined(reason) => ApiError::PaymentDeclined(reason),
+            PaymentError::Unavailable(message) => ApiError::PaymentGatewayError(message),
+        }
+    }
+}
+
+/// A payment provider. Calls are made from blocking code, inside the
+/// transaction that records their outcome, so a failed call rolls back the
+/// change that needed it. Captures, voids and refunds are made last, once
+/// everything else in the transaction has succeeded (see `GatewayCall`).
+pub trait PaymentGateway: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Holds `amount` on the payment method and returns the reference of the
+    /// authorization.
+    fn authorize(
+        &self,
+        order_id: i32,
+        amount: Money,
+        payment_method: Option<&str>,
+    ) -> Result<String, PaymentError>;
+
+    fn capture(&self, reference: &str, amount: Money) -> Result<(), PaymentError>;
+
+    fn void(&self, reference: &str) -> Result<(), PaymentError>;
+
+    fn refund(&self, reference: &str, amount: Money) -> Result<(), PaymentError>;
+}
+
+pub const MOCK_DECLINED: &str = "mock_declined";
+pub const MOCK_INSUFFICIENT_FUNDS: &str = "mock_insufficient_funds";
+pub const MOCK_UNAVAILABLE: &str = "mock_unavailable";
+const MOCK_REFERENCE_PREFIX: &str = "mock_auth_";
+
+/// In-process gateway for local use and testing. Every payment method is
+/// approved except `mock_declined`, `mock_insufficient_funds` and
+/// `mock_unavailable`, and references are derived from the order and amount,
+/// so the same request always gets the same answer.
+pub struct MockPaymentGateway;
+
+impl MockPaymentGateway {
+    fn check_reference(reference: &str) -> Result<(), PaymentError> {
+        if !reference.starts_with(MOCK_REFERENCE_PREFIX) {
+            return Err(PaymentError::Declined("unknown_authorization".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl PaymentGateway for MockPaymentGateway {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn authorize(
+        &self,
+        order_id: i32,
+        amount: Money,
+        payment_method: Option<&str>,
+    ) -> Result<String, PaymentError> {
+        match payment_method {
+            Some(MOCK_DECLINED) => Err(PaymentError::Declined("card_declined".to_string())),
+            Some(MOCK_INSUFFICIENT_FUNDS) => {
+                Err(PaymentError::Declined("insufficient_funds".to_string()))
+            }
+            Some(MOCK_UNAVAILABLE) => Err(PaymentError::Unavailable(
+                "mock gateway is unavailable".to_string(),
+            )),
+            _ => Ok(format!("{}{}_{}", MOCK_REFERENCE_PREFIX, order_id, amount)),
+        }
+    }
+
+    fn capture(&self, reference: &str, _amount: Money) -> Result<(), PaymentError> {
+        Self::check_reference(reference)
+    }
+
+    fn void(&self, reference: &str) -> Result<(), PaymentError> {
+        Self::check_reference(reference)
+    }
+
+    fn refund(&self, reference: &str, _amount: Money) -> Result<(), PaymentError> {
+        Self::check_reference(reference)
+    }
+}
+
+/// An authorization to void, run by the outbox relay once the transaction
+/// that queued it has committed. A void the gateway refuses is retried like
+/// any other outbox message.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct VoidAuthorization {
+    pub reference: String,
+}
+
+impl Job for VoidAuthorization {
+    const NAME: &'static str = "VoidAuthorization";
+}
+
+/// A capture, void or refund that a saved payment change still has to make.
+/// The payment row is updated first and the call is made as the last step of
+/// the transaction, so a rolled back change never reaches the gateway and a
+/// failed call rolls the change back.
+#[must_use]
+pub enum GatewayCall {
+    Capture { reference: String, amount: Money },
+    Void { reference: String },
+    Refund { reference: String, amount: Money },
+}
+
+/// Makes the gateway call of a payment change, if it needs one.
+pub fn run_gateway_call(
+    gateway: &dyn PaymentGateway,
+    gateway_call: Option<GatewayCall>,
+) -> Result<(), DbError> {
+    let result = match gateway_call {
+        None => return Ok(()),
+        Some(GatewayCall::Capture { reference, amount }) => gateway.capture(&reference, amount),
+        Some(GatewayCall::Void { reference }) => gateway.void(&reference),
+        Some(GatewayCall::Refund { reference, amount }) => gateway.refund(&reference, amount),
+    };
+    result.map_err(ApiError::from)?;
+    Ok(())
+}
+
+/// Queues the void of an authorization whose transaction was rolled back. It
+/// runs outside any transaction; should even that fail, the reference is
+/// logged so the authorization can be voided by hand.
+pub fn void_after_rollback(conn: &mut PgConnection, reference: &str) {
+    let void_authorization = VoidAuthorization {
+        reference: reference.to_string(),
+    };
+    if let Err(err) = outbox::enqueue(conn, &void_authorization) {
+        tracing::error!(
+            "Could not queue the void of authorization {}: {}",
+            reference,
+            err
+        );
+    }
+}
+
+pub fn get_payment_by_order_id(
+    conn: &mut PgConnection,
+    _order_id: i32,
+) -> Result<Option<Payment>, DbError> {
+    let payment = schema::payments::table
+        .filter(schema::payments::order_id.eq(_order_id))
+        .select(Payment::as_select())
+        .first::<Payment>(conn)
+        .optional()?;
+    Ok(payment)
+}
+
+fn lock_payment(conn: &mut PgConnection, _order_id: i32) -> Result<Option<Payment>, DbError> {
+    let payment = schema::payments::table
+        .filter(schema::payments::order_id.eq(_order_id))
+        .select(Payment::as_select())
+        .for_update()
+        .first::<Payment>(conn)
+        .optional()?;
+    Ok(payment)
+}
+
+fn check_payment_status(payment: &Payment, expected: PaymentStatus) -> Result<(), DbError> {
+    if payment.status != expected {
+        return Err(ApiError::Conflict(format!(
+            "Payment of order {} is {}, expected {}",
+            payment.order_id,
+            payment.status.as_str(),
+            expected.as_str()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Authorizes the order's discounted price and records the payment. The
+/// reference is put in `authorization` as soon as the gateway returns it, so
+/// the caller can void it when the transaction fails.
+pub fn authorize_order_payment(
+    conn: &mut PgConnection,
+    gateway: &dyn PaymentGateway,
+    order: &Order,
+    payment_method: Option<String>,
+    authorization: &mut Option<String>,
+) -> Result<Payment, DbError> {
+    let reference = gateway
+        .authorize(order.id, order.discounted_price, payment_method.as_deref())
+        .map_err(ApiError::from)?;
+    *authorization = Some(reference.clone());
+    let new_payment = NewPayment {
+        order_id: order.id,
+        gateway: gateway.name().to_string(),
+        payment_method,
+        reference,
+        status: PaymentStatus::Authorized,
+        amount: order.discounted_price,
+    };
+    let payment = diesel::insert_into(schema::payments::table)
+        .values(&new_payment)
+        .returning(Payment::as_returning())
+        .get_result(conn)?;
+    Ok(payment)
+}
+
+/// Authorizes the new price of an edited order and moves the payment onto the
+/// new authorization. The previous one is voided once the transaction
+/// commits; the new reference is put in `authorization` so the caller can
+/// void that one instead when the transaction fails. Nothing changes when the
+/// price is the same or the order has no payment.
+pub fn reauthorize_order_payment(
+    conn: &mut PgConnection,
+    gateway: &dyn PaymentGateway,
+    order: &Order,
+    authorization: &mut Option<String>,
+) -> Result<(), DbError> {
+    let Some(payment) = lock_payment(conn, order.id)? else {
+        return Ok(());
+    };
+    if payment.amount == order.discounted_price {
+        return Ok(());
+    }
+    check_payment_status(&payment, PaymentStatus::Authorized)?;
+
+    let new_reference = gateway
+        .authorize(
+            order.id,
+            order.discounted_price,
+            payment.payment_method.as_deref(),
+        )
+        .map_err(ApiError::from)?;
+    *authorization = Some(new_reference.clone());
+    diesel::update(schema::payments::table.find(payment.id))
+        .set((
+            schema::payments::reference.eq(new_reference),
+            schema::payments::amount.eq(order.discounted_price),
+        ))
+        .execute(conn)?;
+    outbox::enqueue(
+        conn,
+        &VoidAuthorization {
+            reference: payment.reference,
+        },
+    )?;
+    Ok(())
+}
+
+/// Captures the authorized amount once the order is paid.
+pub fn capture_order_payment(
+    conn: &mut PgConnection,
+    _order_id: i32,
+) -> Result<Option<GatewayCall>, DbError> {
+    let Some(payment) = lock_payment(conn, _order_id)? else {
+        return Ok(None);
+    };
+    check_payment_status(&payment, PaymentStatus::Authorized)?;
+
+    diesel::update(schema::payments::table.find(payment.id))
+        .set((
+            schema::payments::status.eq(PaymentStatus::Captured),
+            schema::payments::captured_amount.eq(payment.amount),
+        ))
+        .execute(conn)?;
+    Ok(Some(GatewayCall::Capture {
+        reference: payment.reference,
+        amount: payment.amount,
+    }))
+}
+
+/// Refunds `amount` of a captured payment, or all that is left of it when no
+/// amount is given. The payment becomes refunded once nothing is left.
+pub fn refund_order_payment(
+    conn: &mut PgConnection,
+    _order_id: i32,
+    amount: Option<Money>,
+) -> Result<Option<GatewayCall>, DbError> {
+    let Some(payment) = lock_payment(conn, _order_id)? else {
+        return Ok(None);
+    };
+    if payment.status != PaymentStatus::Captured {
+        return Ok(None);
+    }
+    let remaining = payment.captured_amount - payment.refunded_amount;
+    let refund = amount.unwrap_or(remaining).min(remaining);
+    if refund <= Money::ZERO {
+        return Ok(None);
+    }
+
+    let refunded = payment.refunded_amount + refund;
+    let next_status = if refunded == payment.captured_amount {
+        PaymentStatus::Refunded
+    } else {
+        PaymentStatus::Captured
+    };
+    diesel::update(schema::payments::table.find(payment.id))
+        .set((
+            schema::payments::status.eq(next_status),
+            schema::payments::refunded_amount.eq(refunded),
+        ))
+        .execute(conn)?;
+    Ok(Some(GatewayCall::Refund {
+        reference: payment.reference,
+        amount: refund,
+    }))
+}
+
+/// Gives the money back for a cancelled order: an authorization is voided and
+/// a captured payment is refunded in full.
+pub fn release_order_payment(
+    conn: &mut PgConnection,
+    _order_id: i32,
+) -> Result<Option<GatewayCall>, DbError> {
+    let Some(payment) = lock_payment(conn, _order_id)? else {
+        return Ok(None);
+    };
+    match payment.status {
+        PaymentStatus::Authorized => {
+            diesel::update(schema::payments::table.find(payment.id))
+                .set(schema::payments::status.eq(PaymentStatus::Voided))
+                .execute(conn)?;
+            Ok(Some(GatewayCall::Void {
+                reference: payment.reference,
+            }))
+        }
+        PaymentStatus::Captured => refund_order_payment(conn, _order_id, None),
+        PaymentStatus::Voided | PaymentStatus::Refunded => Ok(None),
+    }
+}
@@ -1,6 +1,8 @@
 use crate::insertables::NewProduct;
 use actix_web::{delete, error, get, post, web, HttpResponse, Responder, Result};
+use chrono::NaiveDateTime;
 use diesel::{prelude::*, r2d2};
+use rust_order_api::models::Money;
 use rust_order_api::schema;
 use schema::products::dsl::*;
 use serde::Serialize;
@@ -13,8 +15,11 @@ pub struct ProductWithCategory {
     pub title: String,
     pub category_id: i32,
     pub author: String,
-    pub list_price: f64,
+    pub list_price: Money,
     pub stock_quantity: i32,
+    pub weight_grams: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
     pub category: CategoryTitle,
 }
 
@@ -34,9 +39,23 @@ pub fn get_all_products(conn: &mut PgConnection) -> Result<Vec<ProductWithCatego
             schema::products::author,
             schema::products::list_price,
             schema::products::stock_quantity,
+            schema::products::weight_grams,
+            schema::products::created_at,
+            schema::products::updated_at,
             schema::categories::title,
         ))
-        .load::<(i32, String, i32, String, f64, i32, String)>(conn)?
+        .load::<(
+            i32,
+            String,
+            i32,
+            String,
+            Money,
+            i32,
+            i32,
+            NaiveDateTime,
+            NaiveDateTime,
+            String,
+        )>(conn)?
         .into_iter()
         .map(
             |(
@@ -46,6 +65,9 @@ pub fn get_all_products(conn: &mut PgConnection) -> Result<Vec<ProductWithCatego
                 product_author,
                 product_list_price,
                 product_stock_quantity,
+                product_weight_grams,
+                product_created_at,
+                product_updated_at,
                 category_info,
             )| {
                 let category = CategoryTitle {
@@ -59,6 +81,9 @@ pub fn get_all_products(conn: &mut PgConnection) -> Result<Vec<ProductWithCatego
                     author: product_author,
                     list_price: product_list_price,
                     stock_quantity: product_stock_quantity,
+                    weight_grams: product_weight_grams,
+                    created_at: product_created_at,
+                    updated_at: product_updated_at,
                     category,
                 }
             },
@@ -83,9 +108,23 @@ pub fn get_product_by_id(
             schema::products::author,
             schema::products::list_price,
             schema::products::stock_quantity,
+            schema::products::weight_grams,
+            schema::products::created_at,
+            schema::products::updated_at,
             schema::categories::title,
         ))
-        .first::<(i32, String, i32, String, f64, i32, String)>(conn)?;
+        .first::<(
+            i32,
+            String,
+            i32,
+            String,
+            Money,
+            i32,
+            i32,
+            NaiveDateTime,
+            NaiveDateTime,
+            String,
+        )>(conn)?;
 
     let (
         product_id,
@@ -94,6 +133,9 @@ pub fn get_product_by_id(
         product_author,
         product_list_price,
         product_stock_quantity,
+        product_weight_grams,
+        product_created_at,
+        product_updated_at,
         category_info,
     ) = product_with_category;
     let category = CategoryTitle {
@@ -107,6 +149,9 @@ pub fn get_product_by_id(
         author: product_author,
         list_price: product_list_price,
         stock_quantity: product_stock_quantity,
+        weight_grams: product_weight_grams,
+        created_at: product_created_at,
+        updated_at: product_updated_at,
         category,
     })
 }
@@ -116,8 +161,9 @@ pub fn insert_new_product(
     _title: &str,
     _category_id: i32,
     _author: &str,
-    _list_price: f64,
+    _list_price: Money,
     _stock_quantity: i32,
+    _weight_grams: i32,
 ) -> Result<NewProduct, DbError> {
     let new_product = NewProduct {
         title: _title.to_owned(),
@@ -125,6 +171,7 @@ pub fn insert_new_product(
         author: _author.to_owned(),
         list_price: _list_price.to_owned(),
         stock_quantity: _stock_quantity.to_owned(),
+        weight_grams: _weight_grams.to_owned(),
     };
     diesel::insert_into(products)
         .values(&new_product)
@@ -176,6 +223,7 @@ async fn create_product(
             &form.author,
             form.list_price,
             form.stock_quantity,
+            form.weight_grams,
         )
     })
     .await?
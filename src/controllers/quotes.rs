@@ -0,0 +1,95 @@
+use crate::controllers::campaigns::get_cached_campaigns;
+use crate::controllers::errors;
+use crate::controllers::functions;
+use crate::controllers::orders::{self, OrderItem};
+use crate::controllers::shipping::get_active_shipping_method;
+use actix_web::{post, web, HttpResponse, Responder, Result};
+use diesel::r2d2::Pool;
+use diesel::{prelude::*, r2d2};
+use r2d2_redis::{redis, RedisConnectionManager};
+use serde::Deserialize;
+use serde_json::{json, Value};
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+#[derive(Deserialize)]
+pub struct QuoteDto {
+    #[serde(default)]
+    pub items: Vec<OrderItem>,
+    #[serde(default)]
+    pub product_ids: Vec<i32>,
+    #[serde(default)]
+    pub shipping_method: Option<String>,
+}
+
+pub fn get_quote(
+    conn: &mut PgConnection,
+    redis_conn: &mut redis::Connection,
+    items: Vec<OrderItem>,
+    shipping_method: Option<&str>,
+) -> Result<Value, DbError> {
+    let items = orders::merge_order_items(&items)?;
+    let quote_products = orders::load_order_products(conn, &items)?;
+    let shipping_method = get_active_shipping_method(conn, shipping_method)?;
+    let all_campaigns = get_cached_campaigns(conn, redis_conn)?;
+    let pricing = functions::get_order_pricing(all_campaigns, &quote_products, shipping_method)?;
+
+    let campaign_json = |campaign_price: &functions::CampaignPrice| {
+        json!({
+            "id": campaign_price.campaign.id,
+            "description": campaign_price.campaign.description,
+            "discounted_price": campaign_price.discounted_price,
+        })
+    };
+
+    let quote_json = json!({
+        "subtotal": pricing.subtotal,
+        "shipping_method": pricing.shipping_method.code,
+        "shipping_cost": pricing.shipping_cost,
+        "price_without_discount": pricing.price_without_discount,
+        "discounted_price": pricing.discounted_price,
+        "campaign_id": pricing.campaign_id,
+        "best_campaign": pricing
+            .campaign_prices
+            .iter()
+            .find(|campaign_price| Some(campaign_price.campaign.id) == pricing.campaign_id)
+            .map(campaign_json),
+        "campaigns": pricing.campaign_prices.iter().map(campaign_json).collect::<Vec<_>>(),
+        "products": quote_products.iter().map(|product| {
+            json!({
+                "id": product.product.id,
+                "title": product.product.title,
+                "author": product.product.author,
+                "list_price": product.product.list_price,
+                "quantity": product.quantity,
+                "in_stock": product.product.stock_quantity >= product.quantity,
+                "category": {
+                    "title": product.category_title,
+                },
+            })
+        }).collect::<Vec<_>>(),
+    });
+
+    Ok(quote_json)
+}
+
+#[post("/api/quotes")]
+async fn create_quote(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    form: web::Json<QuoteDto>,
+) -> Result<impl Responder> {
+    let quote = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        get_quote(
+            &mut db_conn,
+            &mut redis_conn,
+            orders::get_line_items(&form.items, &form.product_ids),
+            form.shipping_method.as_deref(),
+        )
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(quote))
+}
@@ -0,0 +1,188 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::orders::{self, OrderDto, OrderItem};
+use crate::controllers::payments::PaymentGateway;
+use crate::controllers::quotes;
+use actix_web::{post, web, HttpResponse, Responder, Result};
+use diesel::r2d2::Pool;
+use diesel::{prelude::*, r2d2};
+use r2d2_redis::RedisConnectionManager;
+use rust_order_api::models::{Order, OrderStatus, OrderToProduct};
+use rust_order_api::schema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+#[derive(Deserialize, Default)]
+pub struct ReorderDto {
+    #[serde(default)]
+    pub quote: bool,
+    #[serde(default)]
+    pub shipping_method: Option<String>,
+    #[serde(default)]
+    pub shipping_address_id: Option<i32>,
+    #[serde(default)]
+    pub billing_address_id: Option<i32>,
+    #[serde(default)]
+    pub payment_method: Option<String>,
+}
+
+pub struct ReorderItems {
+    pub order: Order,
+    pub items: Vec<OrderItem>,
+    pub skipped: Vec<Value>,
+}
+
+fn get_snapshot_address_id(address: &Option<Value>) -> Option<i32> {
+    address
+        .as_ref()
+        .and_then(|address| address["id"].as_i64())
+        .map(|address_id| address_id as i32)
+}
+
+/// Splits the lines of a past order into the ones that can be bought again
+/// in full and the ones that are skipped because the product was deleted or
+/// does not have enough stock left.
+pub fn get_reorder_items(conn: &mut PgConnection, _order_id: i32) -> Result<ReorderItems, DbError> {
+    use schema::products::dsl::*;
+
+    let order = schema::orders::table
+        .filter(schema::orders::id.eq(_order_id))
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", _order_id)))?;
+    if order.status == OrderStatus::Processing {
+        return Err(
+            ApiError::Conflict(format!("Order {} is still being processed", _order_id)).into(),
+        );
+    }
+    let order_lines = OrderToProduct::belonging_to(&order)
+        .select(OrderToProduct::as_select())
+        .order(schema::orders_products::product_id)
+        .load::<OrderToProduct>(conn)?;
+
+    let line_product_ids: Vec<i32> = order_lines.iter().map(|line| line.product_id).collect();
+    let stock_quantities: HashMap<i32, i32> = products
+        .filter(id.eq_any(&line_product_ids))
+        .select((id, stock_quantity))
+        .load::<(i32, i32)>(conn)?
+        .into_iter()
+        .collect();
+
+    let mut items = Vec::new();
+    let mut skipped = Vec::new();
+    for line in &order_lines {
+        match stock_quantities.get(&line.product_id) {
+            None => skipped.push(json!({
+                "product_id": line.product_id,
+                "title": line.title,
+                "quantity": line.quantity,
+                "reason": "deleted",
+            })),
+            Some(available_quantity) if *available_quantity < line.quantity => {
+                skipped.push(json!({
+                    "product_id": line.product_id,
+                    "title": line.title,
+                    "quantity": line.quantity,
+                    "available_quantity": available_quantity,
+                    "reason": "out_of_stock",
+                }))
+            }
+            Some(_) => items.push(OrderItem {
+                product_id: line.product_id,
+                quantity: line.quantity,
+            }),
+        }
+    }
+
+    if items.is_empty() {
+        return Err(ApiError::OutOfStock(line_product_ids).into());
+    }
+
+    Ok(ReorderItems {
+        order,
+        items,
+        skipped,
+    })
+}
+
+fn get_reorder_dto(
+    order: &Order,
+    items: Vec<OrderItem>,
+    form: ReorderDto,
+) -> Result<OrderDto, DbError> {
+    let shipping_address_id = form
+        .shipping_address_id
+        .or_else(|| get_snapshot_address_id(&order.shipping_address))
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "Order {} has no shipping address, shipping_address_id is required",
+                order.id
+            ))
+        })?;
+    Ok(OrderDto {
+        user_id: order.user_id,
+        items,
+        product_ids: vec![],
+        shipping_method: form
+            .shipping_method
+            .or_else(|| Some(order.shipping_method.clone())),
+        shipping_address_id,
+        billing_address_id: form
+            .billing_address_id
+            .or_else(|| get_snapshot_address_id(&order.billing_address)),
+        payment_method: form.payment_method,
+    })
+}
+
+#[post("/api/orders/{order_id}/reorder")]
+async fn reorder(
+    db_pool: web::Data<DbPool>,
+    redis_pool: web::Data<Pool<RedisConnectionManager>>,
+    gateway: web::Data<dyn PaymentGateway>,
+    order_id: web::Path<i32>,
+    form: Option<web::Json<ReorderDto>>,
+) -> Result<impl Responder> {
+    let _order_id = order_id.into_inner();
+    let form = form.map(|form| form.into_inner()).unwrap_or_default();
+
+    if form.quote {
+        let quote = web::block(move || {
+            let mut db_conn = db_pool.get()?;
+            let mut redis_conn = redis_pool.get()?;
+            let reorder_items = get_reorder_items(&mut db_conn, _order_id)?;
+            let shipping_method = form
+                .shipping_method
+                .unwrap_or(reorder_items.order.shipping_method);
+            let quote = quotes::get_quote(
+                &mut db_conn,
+                &mut redis_conn,
+                reorder_items.items,
+                Some(&shipping_method),
+            )?;
+            Ok::<_, DbError>(json!({
+                "quote": quote,
+                "skipped": reorder_items.skipped,
+            }))
+        })
+        .await?
+        .map_err(errors::into_response_error)?;
+        return Ok(HttpResponse::Ok().json(quote));
+    }
+
+    let (skipped, order) = web::block(move || {
+        let mut db_conn = db_pool.get()?;
+        let mut redis_conn = redis_pool.get()?;
+        let reorder_items = get_reorder_items(&mut db_conn, _order_id)?;
+        let order = get_reorder_dto(&reorder_items.order, reorder_items.items, form)?;
+        let order = orders::insert_new_order(&mut db_conn, &mut redis_conn, &**gateway, order)?;
+        Ok::<_, DbError>((reorder_items.skipped, order))
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Created().json(json!({
+        "order": order,
+        "skipped": skipped,
+    })))
+}
@@ -0,0 +1,146 @@
+use std::env;
+use std::future::Future;
+use std::time::Duration;
+
+const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+const DEFAULT_BACKOFF_MS: u64 = 1_000;
+const DEFAULT_MAX_BACKOFF_MS: u64 = 60_000;
+
+/// Retry policy for background jobs. A failed job is run again after
+/// `base_delay`, doubling the wait after every further failure up to
+/// `max_delay`, until it has run `max_attempts` times.
+///
+/// The retries happen inside the job handler: the apalis 0.4 redis storage
+/// acknowledges a job once the handler returns, whatever the outcome, and its
+/// ack service cannot be wrapped by tower's retry layer.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: i32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Reads `{prefix}_MAX_ATTEMPTS`, `{prefix}_BACKOFF_MS` and
+    /// `{prefix}_MAX_BACKOFF_MS`, falling back to 5 attempts, 1s and 60s.
+    pub fn from_env(prefix: &str) -> Self {
+        fn read<T: std::str::FromStr>(name: String, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+        RetryPolicy {
+            max_attempts: read(format!("{}_MAX_ATTEMPTS", prefix), DEFAULT_MAX_ATTEMPTS).max(1),
+            base_delay: Duration::from_millis(read(
+                format!("{}_BACKOFF_MS", prefix),
+                DEFAULT_BACKOFF_MS,
+            )),
+            max_delay: Duration::from_millis(read(
+                format!("{}_MAX_BACKOFF_MS", prefix),
+                DEFAULT_MAX_BACKOFF_MS,
+            )),
+        }
+    }
+
+    pub fn backoff(&self, failed_attempts: i32) -> Duration {
+        let exponent = (failed_attempts - 1).clamp(0, 31) as u32;
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay)
+    }
+
+    /// Calls `attempt` with the attempt number until it succeeds or the policy
+    /// runs out of attempts, sleeping for the backoff in between. Returns the
+    /// last result along with the number of attempts made.
+    pub async fn retry<T, E, F, Fut>(&self, mut attempt: F) -> (Result<T, E>, i32)
+    where
+        F: FnMut(i32) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match attempt(attempts).await {
+                Err(_) if attempts < self.max_attempts => {
+                    actix_web::rt::time::sleep(self.backoff(attempts)).await;
+                }
+                result => return (result, attempts),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_attempts: i32, base_delay_ms: u64, max_delay_ms: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(base_delay_ms),
+            max_delay: Duration::from_millis(max_delay_ms),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_after_every_failure() {
+        let policy = policy(5, 100, 60_000);
+        let delays: Vec<u128> = (1..=4)
+            .map(|failed_attempts| policy.backoff(failed_attempts).as_millis())
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800]);
+    }
+
+    #[test]
+    fn backoff_is_capped_at_the_max_delay() {
+        let policy = policy(5, 1_000, 5_000);
+        assert_eq!(policy.backoff(3), Duration::from_millis(4_000));
+        assert_eq!(policy.backoff(4), Duration::from_millis(5_000));
+        assert_eq!(policy.backoff(i32::MAX), Duration::from_millis(5_000));
+    }
+
+    #[test]
+    fn backoff_before_the_first_retry_is_the_base_delay() {
+        let policy = policy(5, 250, 60_000);
+        assert_eq!(policy.backoff(0), Duration::from_millis(250));
+        assert_eq!(policy.backoff(1), Duration::from_millis(250));
+    }
+
+    #[actix_web::test]
+    async fn retry_stops_after_max_attempts() {
+        let mut seen_attempts = vec![];
+        let (result, attempts) = policy(3, 0, 0)
+            .retry(|attempt| {
+                seen_attempts.push(attempt);
+                async move { Err::<(), _>(attempt) }
+            })
+            .await;
+        assert_eq!(result, Err(3));
+        assert_eq!(attempts, 3);
+        assert_eq!(seen_attempts, vec![1, 2, 3]);
+    }
+
+    #[actix_web::test]
+    async fn retry_stops_at_the_first_success() {
+        let (result, attempts) = policy(5, 0, 0)
+            .retry(|attempt| async move {
+                match attempt {
+                    1 => Err("unavailable"),
+                    _ => Ok(attempt),
+                }
+            })
+            .await;
+        assert_eq!(result, Ok(2));
+        assert_eq!(attempts, 2);
+    }
+
+    #[actix_web::test]
+    async fn single_attempt_policy_does_not_retry() {
+        let (result, attempts) = policy(1, 0, 0)
+            .retry(|_| async { Err::<(), _>("unavailable") })
+            .await;
+        assert_eq!(result, Err("unavailable"));
+        assert_eq!(attempts, 1);
+    }
+}
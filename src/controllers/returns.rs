@@ -0,0 +1,385 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::functions;
+use crate::controllers::orders::{self, OrderItem};
+use crate::controllers::payments::{self, PaymentGateway};
+use actix_web::{get, post, web, HttpResponse, Responder, Result};
+use diesel::{prelude::*, r2d2};
+use rust_order_api::models::{
+    Money, Order, OrderReturn, OrderReturnItem, OrderStatus, OrderToProduct, ReturnStatus,
+};
+use rust_order_api::schema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+#[derive(Deserialize)]
+pub struct ReturnDto {
+    pub items: Vec<OrderItem>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ResolveReturnDto {
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+fn lock_order(conn: &mut PgConnection, _order_id: i32) -> Result<Order, DbError> {
+    use schema::orders::dsl::*;
+    let order = orders
+        .filter(id.eq(_order_id))
+        .for_update()
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", _order_id)))?;
+    Ok(order)
+}
+
+fn lock_requested_return(conn: &mut PgConnection, return_id: i32) -> Result<OrderReturn, DbError> {
+    use schema::order_returns::dsl::*;
+    let order_return = order_returns
+        .filter(id.eq(return_id))
+        .select(OrderReturn::as_select())
+        .for_update()
+        .first::<OrderReturn>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Return {} not found", return_id)))?;
+    if order_return.status != ReturnStatus::Requested {
+        return Err(ApiError::Conflict(format!(
+            "Return {} is already {}",
+            return_id,
+            order_return.status.as_str()
+        ))
+        .into());
+    }
+    Ok(order_return)
+}
+
+fn get_order_lines(
+    conn: &mut PgConnection,
+    order: &Order,
+) -> Result<HashMap<i32, OrderToProduct>, DbError> {
+    let order_lines = OrderToProduct::belonging_to(order)
+        .select(OrderToProduct::as_select())
+        .load::<OrderToProduct>(conn)?
+        .into_iter()
+        .map(|line| (line.product_id, line))
+        .collect();
+    Ok(order_lines)
+}
+
+fn get_returned_value(order_lines: &HashMap<i32, OrderToProduct>) -> Money {
+    order_lines
+        .values()
+        .map(|line| line.unit_price * Money::from(line.returned_quantity))
+        .sum()
+}
+
+fn get_return_json(conn: &mut PgConnection, order_return: &OrderReturn) -> Result<Value, DbError> {
+    use schema::orders::dsl::*;
+    let order = orders
+        .filter(id.eq(order_return.order_id))
+        .first::<Order>(conn)?;
+    let order_lines = get_order_lines(conn, &order)?;
+    let return_items = OrderReturnItem::belonging_to(order_return)
+        .select(OrderReturnItem::as_select())
+        .order(schema::order_return_items::product_id)
+        .load::<OrderReturnItem>(conn)?;
+
+    Ok(json!({
+        "id": order_return.id,
+        "order_id": order_return.order_id,
+        "status": order_return.status,
+        "reason": order_return.reason,
+        "resolution_note": order_return.resolution_note,
+        "refund_amount": order_return.refund_amount,
+        "resolved_at": order_return.resolved_at,
+        "created_at": order_return.created_at,
+        "updated_at": order_return.updated_at,
+        "items": return_items.iter().map(|item| {
+            let line = order_lines.get(&item.product_id);
+            json!({
+                "product_id": item.product_id,
+                "title": line.map(|line| &line.title),
+                "unit_price": line.map(|line| line.unit_price),
+                "quantity": item.quantity,
+            })
+        }).collect::<Vec<_>>(),
+    }))
+}
+
+pub fn insert_new_return(
+    conn: &mut PgConnection,
+    _order_id: i32,
+    return_request: &ReturnDto,
+) -> Result<Value, DbError> {
+    let items = orders::merge_order_items(&return_request.items)?;
+
+    conn.transaction::<_, DbError, _>(|conn| {
+        let order = lock_order(conn, _order_id)?;
+        if order.status != OrderStatus::Delivered {
+            return Err(ApiError::Conflict(format!(
+                "Only delivered orders can be returned, order {} is {}",
+                _order_id,
+                order.status.as_str()
+            ))
+            .into());
+        }
+
+        let order_lines = get_order_lines(conn, &order)?;
+        let pending_quantities: HashMap<i32, i64> = schema::order_return_items::table
+            .inner_join(schema::order_returns::table)
+            .filter(schema::order_returns::order_id.eq(_order_id))
+            .filter(schema::order_returns::status.eq(ReturnStatus::Requested))
+            .group_by(schema::order_return_items::product_id)
+            .select((
+                schema::order_return_items::product_id,
+                diesel::dsl::sum(schema::order_return_items::quantity),
+            ))
+            .load::<(i32, Option<i64>)>(conn)?
+            .into_iter()
+            .map(|(_product_id, quantity)| (_product_id, quantity.unwrap_or(0)))
+            .collect();
+
+        for item in &items {
+            let line = order_lines.get(&item.product_id).ok_or_else(|| {
+                ApiError::BadRequest(format!(
+                    "Product {} is not part of order {}",
+                    item.product_id, _order_id
+                ))
+            })?;
+            let returnable_quantity = (line.quantity - line.returned_quantity) as i64
+                - pending_quantities
+                    .get(&item.product_id)
+                    .copied()
+                    .unwrap_or(0);
+            if item.quantity as i64 > returnable_quantity {
+                return Err(ApiError::BadRequest(format!(
+                    "Only {} of product {} can be returned",
+                    returnable_quantity.max(0),
+                    item.product_id
+                ))
+                .into());
+            }
+        }
+
+        let order_return: OrderReturn = diesel::insert_into(schema::order_returns::table)
+            .values((
+                schema::order_returns::order_id.eq(_order_id),
+                schema::order_returns::reason.eq(&return_request.reason),
+            ))
+            .returning(OrderReturn::as_returning())
+            .get_result(conn)?;
+
+        for item in &items {
+            diesel::insert_into(schema::order_return_items::table)
+                .values((
+                    schema::order_return_items::order_return_id.eq(order_return.id),
+                    schema::order_return_items::product_id.eq(item.product_id),
+                    schema::order_return_items::quantity.eq(item.quantity),
+                ))
+                .execute(conn)?;
+        }
+
+        get_return_json(conn, &order_return)
+    })
+}
+
+pub fn get_returns_by_order_id(
+    conn: &mut PgConnection,
+    _order_id: i32,
+) -> Result<Vec<Value>, DbError> {
+    use schema::orders::dsl::*;
+    let order = orders
+        .filter(id.eq(_order_id))
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Order {} not found", _order_id)))?;
+    let order_returns = OrderReturn::belonging_to(&order)
+        .select(OrderReturn::as_select())
+        .order(schema::order_returns::id)
+        .load::<OrderReturn>(conn)?;
+    order_returns
+        .iter()
+        .map(|order_return| get_return_json(conn, order_return))
+        .collect()
+}
+
+pub fn get_return_by_id(conn: &mut PgConnection, return_id: i32) -> Result<Value, DbError> {
+    use schema::order_returns::dsl::*;
+    let order_return = order_returns
+        .filter(id.eq(return_id))
+        .select(OrderReturn::as_select())
+        .first::<OrderReturn>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("Return {} not found", return_id)))?;
+    get_return_json(conn, &order_return)
+}
+
+pub fn approve_return_by_id(
+    conn: &mut PgConnection,
+    gateway: &dyn PaymentGateway,
+    return_id: i32,
+    note: Option<String>,
+) -> Result<Value, DbError> {
+    conn.transaction::<_, DbError, _>(|conn| {
+        let order_return = lock_requested_return(conn, return_id)?;
+        let order = lock_order(conn, order_return.order_id)?;
+        let order_lines = get_order_lines(conn, &order)?;
+        let return_items = OrderReturnItem::belonging_to(&order_return)
+            .select(OrderReturnItem::as_select())
+            .order(schema::order_return_items::product_id)
+            .load::<OrderReturnItem>(conn)?;
+
+        let mut return_value = Money::ZERO;
+        for item in &return_items {
+            let line = &order_lines[&item.product_id];
+            if line.quantity - line.returned_quantity < item.quantity {
+                return Err(ApiError::Conflict(format!(
+                    "Product {} of order {} was already returned",
+                    item.product_id, order.id
+                ))
+                .into());
+            }
+            return_value += line.unit_price * Money::from(item.quantity);
+        }
+
+        let returned_value = get_returned_value(&order_lines);
+        let refund_amount = functions::get_refund_amount(&order, returned_value + return_value)
+            - functions::get_refund_amount(&order, returned_value);
+
+        for item in &return_items {
+            diesel::update(schema::products::table.find(item.product_id))
+                .set(
+                    schema::products::stock_quantity
+                        .eq(schema::products::stock_quantity + item.quantity),
+                )
+                .execute(conn)?;
+            diesel::update(schema::orders_products::table.find((order.id, item.product_id)))
+                .set(
+                    schema::orders_products::returned_quantity
+                        .eq(schema::orders_products::returned_quantity + item.quantity),
+                )
+                .execute(conn)?;
+        }
+
+        let approved_return: OrderReturn =
+            diesel::update(schema::order_returns::table.find(return_id))
+                .set((
+                    schema::order_returns::status.eq(ReturnStatus::Approved),
+                    schema::order_returns::refund_amount.eq(Some(refund_amount)),
+                    schema::order_returns::resolution_note.eq(note),
+                    schema::order_returns::resolved_at.eq(diesel::dsl::now),
+                ))
+                .returning(OrderReturn::as_returning())
+                .get_result(conn)?;
+        let gateway_call = payments::refund_order_payment(conn, order.id, Some(refund_amount))?;
+
+        let fully_returned = get_order_lines(conn, &order)?
+            .values()
+            .all(|line| line.returned_quantity == line.quantity);
+        if fully_returned {
+            orders::transition_order_status(conn, order.id, OrderStatus::Refunded)?;
+        }
+
+        let return_json = get_return_json(conn, &approved_return)?;
+        payments::run_gateway_call(gateway, gateway_call)?;
+        Ok(return_json)
+    })
+}
+
+pub fn reject_return_by_id(
+    conn: &mut PgConnection,
+    return_id: i32,
+    note: Option<String>,
+) -> Result<Value, DbError> {
+    conn.transaction::<_, DbError, _>(|conn| {
+        lock_requested_return(conn, return_id)?;
+        let rejected_return: OrderReturn =
+            diesel::update(schema::order_returns::table.find(return_id))
+                .set((
+                    schema::order_returns::status.eq(ReturnStatus::Rejected),
+                    schema::order_returns::resolution_note.eq(note),
+                    schema::order_returns::resolved_at.eq(diesel::dsl::now),
+                ))
+                .returning(OrderReturn::as_returning())
+                .get_result(conn)?;
+        get_return_json(conn, &rejected_return)
+    })
+}
+
+#[post("/api/orders/{order_id}/returns")]
+async fn create_return(
+    pool: web::Data<DbPool>,
+    order_id: web::Path<i32>,
+    form: web::Json<ReturnDto>,
+) -> Result<impl Responder> {
+    let order_return = web::block(move || {
+        let mut conn = pool.get()?;
+        insert_new_return(&mut conn, *order_id, &form)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Created().json(order_return))
+}
+
+#[get("/api/orders/{order_id}/returns")]
+async fn get_order_returns(
+    pool: web::Data<DbPool>,
+    order_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let order_returns = web::block(move || {
+        let mut conn = pool.get()?;
+        get_returns_by_order_id(&mut conn, *order_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order_returns))
+}
+
+#[get("/api/returns/{return_id}")]
+async fn get_return(pool: web::Data<DbPool>, return_id: web::Path<i32>) -> Result<impl Responder> {
+    let order_return = web::block(move || {
+        let mut conn = pool.get()?;
+        get_return_by_id(&mut conn, *return_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order_return))
+}
+
+#[post("/api/returns/{return_id}/approve")]
+async fn approve_return(
+    pool: web::Data<DbPool>,
+    gateway: web::Data<dyn PaymentGateway>,
+    return_id: web::Path<i32>,
+    form: Option<web::Json<ResolveReturnDto>>,
+) -> Result<impl Responder> {
+    let note = form.map(|form| form.into_inner()).unwrap_or_default().note;
+    let order_return = web::block(move || {
+        let mut conn = pool.get()?;
+        approve_return_by_id(&mut conn, &**gateway, *return_id, note)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order_return))
+}
+
+#[post("/api/returns/{return_id}/reject")]
+async fn reject_return(
+    pool: web::Data<DbPool>,
+    return_id: web::Path<i32>,
+    form: Option<web::Json<ResolveReturnDto>>,
+) -> Result<impl Responder> {
+    let note = form.map(|form| form.into_inner()).unwrap_or_default().note;
+    let order_return = web::block(move || {
+        let mut conn = pool.get()?;
+        reject_return_by_id(&mut conn, *return_id, note)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(order_return))
+}
@@ -0,0 +1,390 @@
+use crate::controllers::orders::OrderItem;
+use r2d2_redis::redis;
+use rust_order_api::models::Money;
+use serde_json::{json, Value};
+use std::env;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_WINDOW_SECONDS: usize = 60 * 60;
+const DEFAULT_MAX_ORDERS_PER_WINDOW: i64 = 10;
+const DEFAULT_MAX_ORDER_VALUE: i64 = 2_000;
+const DEFAULT_MAX_PRODUCT_UNITS: i64 = 20;
+
+/// Limits an order is checked against before it is saved. The order and
+/// unit counts are per user and reset `window_seconds` after the user's first
+/// order in the window.
+pub struct RiskLimits {
+    pub window_seconds: usize,
+    pub max_orders_per_window: i64,
+    pub max_order_value: Money,
+    pub max_product_units: i64,
+}
+
+impl RiskLimits {
+    /// Reads RISK_WINDOW_SECONDS, RISK_MAX_ORDERS_PER_WINDOW,
+    /// RISK_MAX_ORDER_VALUE and RISK_MAX_PRODUCT_UNITS, falling back to an
+    /// hour, 10 orders, 2000 and 20 units.
+    pub fn from_env() -> Self {
+        Self::from_lookup(&|name| env::var(name).ok())
+    }
+
+    /// Builds the limits from `lookup`, which returns the value of a setting;
+    /// missing or unparsable values fall back to the defaults.
+    fn from_lookup(lookup: &dyn Fn(&str) -> Option<String>) -> Self {
+        fn read<T: std::str::FromStr>(
+            lookup: &dyn Fn(&str) -> Option<String>,
+            name: &str,
+            default: T,
+        ) -> T {
+            lookup(name)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+        RiskLimits {
+            window_seconds: read(lookup, "RISK_WINDOW_SECONDS", DEFAULT_WINDOW_SECONDS).max(1),
+            max_orders_per_window: read(
+                lookup,
+                "RISK_MAX_ORDERS_PER_WINDOW",
+                DEFAULT_MAX_ORDERS_PER_WINDOW,
+            ),
+            max_order_value: read(
+                lookup,
+                "RISK_MAX_ORDER_VALUE",
+                Money::from(DEFAULT_MAX_ORDER_VALUE),
+            ),
+            max_product_units: read(lookup, "RISK_MAX_PRODUCT_UNITS", DEFAULT_MAX_PRODUCT_UNITS),
+        }
+    }
+}
+
+fn get_orders_key(user_id: i32) -> String {
+    format!("risk:orders:{}", user_id)
+}
+
+fn get_product_units_key(user_id: i32, product_id: i32) -> String {
+    format!("risk:product_units:{}:{}", user_id, product_id)
+}
+
+/// Adds `ARGV[1]` to the counter in `KEYS[1]` and returns the new count. A
+/// counter without a TTL gets one of `ARGV[2]` seconds in the same step, so
+/// no counter outlives its window even when a command fails midway. Taking
+/// back from a counter that already expired does nothing.
+const ADJUST_COUNTER_SCRIPT: &str = r#"
+if tonumber(ARGV[1]) < 0 and redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+local count = redis.call('INCRBY', KEYS[1], ARGV[1])
+if redis.call('TTL', KEYS[1]) < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[2])
+end
+return count
+"#;
+
+fn adjust_counter(
+    redis_conn: &mut redis::Connection,
+    key: &str,
+    amount: i64,
+    window_seconds: usize,
+) -> Result<i64, DbError> {
+    let count = redis::Script::new(ADJUST_COUNTER_SCRIPT)
+        .key(key)
+        .arg(amount)
+        .arg(window_seconds)
+        .invoke(redis_conn)?;
+    Ok(count)
+}
+
+/// The counters an order adds to, with the amount it adds: the user's order
+/// count first when it is a new order, then the units of each item.
+fn get_counters(user_id: i32, new_order: bool, items: &[OrderItem]) -> Vec<(String, i64)> {
+    let order_counter = new_order.then(|| (get_orders_key(user_id), 1));
+    order_counter
+        .into_iter()
+        .chain(items.iter().map(|item| {
+            (
+                get_product_units_key(user_id, item.product_id),
+                i64::from(item.quantity),
+            )
+        }))
+        .collect()
+}
+
+fn release_counters(
+    redis_conn: &mut redis::Connection,
+    limits: &RiskLimits,
+    counters: &[(String, i64)],
+) -> Result<(), DbError> {
+    for (key, amount) in counters {
+        adjust_counter(redis_conn, key, -amount, limits.window_seconds)?;
+    }
+    Ok(())
+}
+
+/// The limits broken by an order, given the user's counts with the order
+/// included. `order_count` is `None` when no new order is placed, as for an
+/// edit, which leaves the orders per window alone, and `order_value` is `None`
+/// when the order did not get more expensive.
+fn get_risk_flags(
+    limits: &RiskLimits,
+    order_count: Option<i64>,
+    order_value: Option<Money>,
+    unit_counts: &[(i32, i64)],
+) -> Vec<Value> {
+    let mut risk_flags = vec![];
+
+    if let Some(order_count) = order_count {
+        if order_count > limits.max_orders_per_window {
+            risk_flags.push(json!({
+                "rule": "orders_per_window",
+                "limit": limits.max_orders_per_window,
+                "value": order_count,
+                "window_seconds": limits.window_seconds,
+            }));
+        }
+    }
+
+    if let Some(order_value) = order_value {
+        if order_value > limits.max_order_value {
+            risk_flags.push(json!({
+                "rule": "order_value",
+                "limit": limits.max_order_value,
+                "value": order_value,
+            }));
+        }
+    }
+
+    for (product_id, unit_count) in unit_counts {
+        if *unit_count > limits.max_product_units {
+            risk_flags.push(json!({
+                "rule": "product_units",
+                "product_id": product_id,
+                "limit": limits.max_product_units,
+                "value": unit_count,
+                "window_seconds": limits.window_seconds,
+            }));
+        }
+    }
+
+    risk_flags
+}
+
+/// Counts an order against the user's limits and returns the limits it
+/// exceeds. The counters are incremented atomically and checked with the
+/// counts Redis returns, so concurrent orders of one user each see the ones
+/// before them. The caller undoes the count with `release_order` when the
+/// order is not saved. An edit counts no new order and only the units it adds.
+pub fn check_order(
+    redis_conn: &mut redis::Connection,
+    limits: &RiskLimits,
+    user_id: i32,
+    new_order: bool,
+    items: &[OrderItem],
+    order_value: Option<Money>,
+) -> Result<Vec<Value>, DbError> {
+    let counters = get_counters(user_id, new_order, items);
+    let mut counts = Vec::with_capacity(counters.len());
+    for (key, amount) in &counters {
+        match adjust_counter(redis_conn, key, *amount, limits.window_seconds) {
+            Ok(count) => counts.push(count),
+            Err(err) => {
+                if let Err(release_err) =
+                    release_counters(redis_conn, limits, &counters[..counts.len()])
+                {
+                    tracing::error!(
+                        "Could not release risk counters of user {}: {}",
+                        user_id,
+                        release_err
+                    );
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    let (order_count, unit_counts) = match new_order {
+        true => (Some(counts[0]), &counts[1..]),
+        false => (None, &counts[..]),
+    };
+    let unit_counts: Vec<(i32, i64)> = items
+        .iter()
+        .zip(unit_counts)
+        .map(|(item, unit_count)| (item.product_id, *unit_count))
+        .collect();
+    Ok(get_risk_flags(
+        limits,
+        order_count,
+        order_value,
+        &unit_counts,
+    ))
+}
+
+/// Adds the flags of an edit to the ones already on the order, skipping the
+/// rules the order was already flagged for (per product for `product_units`).
+pub fn add_risk_flags(risk_flags: &mut Vec<Value>, new_risk_flags: &[Value]) {
+    for new_risk_flag in new_risk_flags {
+        let is_flagged = risk_flags.iter().any(|risk_flag| {
+            risk_flag["rule"] == new_risk_flag["rule"]
+                && risk_flag["product_id"] == new_risk_flag["product_id"]
+        });
+        if !is_flagged {
+            risk_flags.push(new_risk_flag.clone());
+        }
+    }
+}
+
+/// Takes back what `check_order` counted for an order that was not saved.
+pub fn release_order(
+    redis_conn: &mut redis::Connection,
+    limits: &RiskLimits,
+    user_id: i32,
+    new_order: bool,
+    items: &[OrderItem],
+) -> Result<(), DbError> {
+    release_counters(redis_conn, limits, &get_counters(user_id, new_order, items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RiskLimits {
+        RiskLimits {
+            window_seconds: 3600,
+            max_orders_per_window: 2,
+            max_order_value: Money::from(500),
+            max_product_units: 5,
+        }
+    }
+
+    fn rules(risk_flags: &[Value]) -> Vec<&str> {
+        risk_flags
+            .iter()
+            .map(|risk_flag| risk_flag["rule"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn limits_fall_back_to_the_defaults() {
+        let limits = RiskLimits::from_lookup(&|name| match name {
+            "RISK_MAX_ORDERS_PER_WINDOW" => Some("3".to_string()),
+            "RISK_MAX_ORDER_VALUE" => Some("not a number".to_string()),
+            _ => None,
+        });
+        assert_eq!(limits.window_seconds, DEFAULT_WINDOW_SECONDS);
+        assert_eq!(limits.max_orders_per_window, 3);
+        assert_eq!(limits.max_order_value, Money::from(DEFAULT_MAX_ORDER_VALUE));
+        assert_eq!(limits.max_product_units, DEFAULT_MAX_PRODUCT_UNITS);
+    }
+
+    #[test]
+    fn window_is_at_least_one_second() {
+        let limits = RiskLimits::from_lookup(&|name| {
+            (name == "RISK_WINDOW_SECONDS").then(|| "0".to_string())
+        });
+        assert_eq!(limits.window_seconds, 1);
+    }
+
+    #[test]
+    fn orders_within_the_limits_are_not_flagged() {
+        let risk_flags = get_risk_flags(
+            &limits(),
+            Some(2),
+            Some(Money::from(500)),
+            &[(1, 5), (2, 1)],
+        );
+        assert!(risk_flags.is_empty());
+    }
+
+    #[test]
+    fn each_broken_limit_is_flagged() {
+        let risk_flags = get_risk_flags(
+            &limits(),
+            Some(3),
+            Some(Money::from(501)),
+            &[(1, 6), (2, 5)],
+        );
+        assert_eq!(
+            rules(&risk_flags),
+            vec!["orders_per_window", "order_value", "product_units"]
+        );
+        assert_eq!(
+            risk_flags[0],
+            json!({
+                "rule": "orders_per_window",
+                "limit": 2,
+                "value": 3,
+                "window_seconds": 3600,
+            })
+        );
+        assert_eq!(risk_flags[2]["product_id"], 1);
+        assert_eq!(risk_flags[2]["value"], 6);
+    }
+
+    #[test]
+    fn new_orders_count_the_order_before_their_units() {
+        let items = [
+            OrderItem {
+                product_id: 4,
+                quantity: 2,
+            },
+            OrderItem {
+                product_id: 9,
+                quantity: 1,
+            },
+        ];
+        assert_eq!(
+            get_counters(7, true, &items),
+            vec![
+                ("risk:orders:7".to_string(), 1),
+                ("risk:product_units:7:4".to_string(), 2),
+                ("risk:product_units:7:9".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            get_counters(7, false, &items[1..]),
+            vec![("risk:product_units:7:9".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn order_value_is_only_checked_when_given() {
+        assert!(get_risk_flags(&limits(), None, None, &[]).is_empty());
+        assert_eq!(
+            rules(&get_risk_flags(
+                &limits(),
+                None,
+                Some(Money::from(501)),
+                &[]
+            )),
+            vec!["order_value"]
+        );
+    }
+
+    #[test]
+    fn flags_already_on_the_order_are_not_added_again() {
+        let mut risk_flags = vec![
+            json!({ "rule": "order_value", "limit": 500, "value": 600 }),
+            json!({ "rule": "product_units", "product_id": 1, "limit": 5, "value": 6 }),
+        ];
+        add_risk_flags(
+            &mut risk_flags,
+            &[
+                json!({ "rule": "order_value", "limit": 500, "value": 700 }),
+                json!({ "rule": "product_units", "product_id": 1, "limit": 5, "value": 8 }),
+                json!({ "rule": "product_units", "product_id": 2, "limit": 5, "value": 6 }),
+            ],
+        );
+        assert_eq!(
+            rules(&risk_flags),
+            vec!["order_value", "product_units", "product_units"]
+        );
+        assert_eq!(risk_flags[0]["value"], 600);
+        assert_eq!(risk_flags[2]["product_id"], 2);
+    }
+
+    #[test]
+    fn edits_do_not_count_as_new_orders() {
+        let risk_flags = get_risk_flags(&limits(), None, None, &[(1, 6)]);
+        assert_eq!(rules(&risk_flags), vec!["product_units"]);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::insertables::NewShippingMethod;
+use actix_web::{get, post, put, web, HttpResponse, Responder, Result};
+use diesel::{prelude::*, r2d2};
+use rust_order_api::models::{Money, ShippingMethod};
+use rust_order_api::schema;
+use schema::shipping_methods::dsl::*;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+pub const DEFAULT_SHIPPING_METHOD: &str = "standard";
+
+fn validate_shipping_method(shipping_method: &NewShippingMethod) -> Result<(), DbError> {
+    if shipping_method.code.trim().is_empty() || shipping_method.name.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "Shipping method code and name must not be empty".to_string(),
+        )
+        .into());
+    }
+    let fees = [
+        Some(shipping_method.base_fee),
+        Some(shipping_method.per_item_fee),
+        Some(shipping_method.per_kg_fee),
+        shipping_method.free_shipping_threshold,
+    ];
+    if fees.iter().flatten().any(|fee| *fee < Money::ZERO) {
+        return Err(ApiError::BadRequest(
+            "Shipping fees and thresholds must not be negative".to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn get_all_shipping_methods(conn: &mut PgConnection) -> Result<Vec<ShippingMethod>, DbError> {
+    let all_shipping_methods = shipping_methods
+        .select(ShippingMethod::as_select())
+        .order(id)
+        .load(conn)?;
+    Ok(all_shipping_methods)
+}
+
+pub fn get_active_shipping_method(
+    conn: &mut PgConnection,
+    shipping_method_code: Option<&str>,
+) -> Result<ShippingMethod, DbError> {
+    let shipping_method_code = shipping_method_code.unwrap_or(DEFAULT_SHIPPING_METHOD);
+    let shipping_method = shipping_methods
+        .filter(code.eq(shipping_method_code))
+        .filter(is_active.eq(true))
+        .select(ShippingMethod::as_select())
+        .first::<ShippingMethod>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "Shipping method {} is not available",
+                shipping_method_code
+            ))
+        })?;
+    Ok(shipping_method)
+}
+
+pub fn get_shipping_method_by_code(
+    conn: &mut PgConnection,
+    shipping_method_code: &str,
+) -> Result<ShippingMethod, DbError> {
+    let shipping_method = shipping_methods
+        .filter(code.eq(shipping_method_code))
+        .select(ShippingMethod::as_select())
+        .first::<ShippingMethod>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Shipping method {} not found",
+                shipping_method_code
+            ))
+        })?;
+    Ok(shipping_method)
+}
+
+pub fn insert_new_shipping_method(
+    conn: &mut PgConnection,
+    new_shipping_method: &NewShippingMethod,
+) -> Result<ShippingMethod, DbError> {
+    validate_shipping_method(new_shipping_method)?;
+    let existing = shipping_methods
+        .filter(code.eq(&new_shipping_method.code))
+        .select(id)
+        .first::<i32>(conn)
+        .optional()?;
+    if existing.is_some() {
+        return Err(ApiError::BadRequest(format!(
+            "Shipping method {} already exists",
+            new_shipping_method.code
+        ))
+        .into());
+    }
+    let shipping_method = diesel::insert_into(shipping_methods)
+        .values(new_shipping_method)
+        .returning(ShippingMethod::as_returning())
+        .get_result(conn)?;
+    Ok(shipping_method)
+}
+
+pub fn update_shipping_method_by_id(
+    conn: &mut PgConnection,
+    shipping_method_id: i32,
+    updated_shipping_method: &NewShippingMethod,
+) -> Result<ShippingMethod, DbError> {
+    validate_shipping_method(updated_shipping_method)?;
+    let duplicate = shipping_methods
+        .filter(code.eq(&updated_shipping_method.code))
+        .filter(id.ne(shipping_method_id))
+        .select(id)
+        .first::<i32>(conn)
+        .optional()?;
+    if duplicate.is_some() {
+        return Err(ApiError::BadRequest(format!(
+            "Shipping method {} already exists",
+            updated_shipping_method.code
+        ))
+        .into());
+    }
+    let shipping_method = diesel::update(shipping_methods.filter(id.eq(shipping_method_id)))
+        .set(updated_shipping_method)
+        .returning(ShippingMethod::as_returning())
+        .get_result(conn)
+        .optional()?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Shipping method {} not found", shipping_method_id))
+        })?;
+    Ok(shipping_method)
+}
+
+#[get("/api/shipping_methods")]
+async fn get_shipping_methods(pool: web::Data<DbPool>) -> Result<impl Responder> {
+    let all_shipping_methods = web::block(move || {
+        let mut conn = pool.get()?;
+        get_all_shipping_methods(&mut conn)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(all_shipping_methods))
+}
+
+#[post("/api/shipping_methods")]
+async fn create_shipping_method(
+    pool: web::Data<DbPool>,
+    form: web::Json<NewShippingMethod>,
+) -> Result<impl Responder> {
+    let shipping_method = web::block(move || {
+        let mut conn = pool.get()?;
+        insert_new_shipping_method(&mut conn, &form)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Created().json(shipping_method))
+}
+
+#[put("/api/shipping_methods/{shipping_method_id}")]
+async fn update_shipping_method(
+    pool: web::Data<DbPool>,
+    shipping_method_id: web::Path<i32>,
+    form: web::Json<NewShippingMethod>,
+) -> Result<impl Responder> {
+    let shipping_method = web::block(move || {
+        let mut conn = pool.get()?;
+        update_shipping_method_by_id(&mut conn, *shipping_method_id, &form)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(shipping_method))
+}
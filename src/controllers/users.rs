@@ -1,7 +1,10 @@
+use crate::controllers::errors::ApiError;
+use crate::controllers::orders::get_order_line_json;
 use crate::insertables::NewUser;
 use actix_web::{delete, error, get, post, web, HttpResponse, Responder, Result};
+use chrono::NaiveDateTime;
 use diesel::{prelude::*, r2d2};
-use rust_order_api::models::{Order, OrderToProduct, Product, User};
+use rust_order_api::models::{Money, Order, OrderStatus, OrderToProduct, User};
 use rust_order_api::schema;
 use schema::users::dsl::*;
 use serde::Serialize;
@@ -14,30 +17,27 @@ type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
 pub struct UserWithOrders {
     pub id: i32,
     pub username: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
     pub orders: Vec<Value>,
 }
 
 #[derive(Queryable, Serialize, Debug, Clone)]
 pub struct OrderWithFields {
     id: i32,
-    price_without_discount: f64,
-    discounted_price: f64,
+    price_without_discount: Money,
+    discounted_price: Money,
     campaign_id: Option<i32>,
     user_id: i32,
+    status: OrderStatus,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
     campaign_description: Option<String>,
 }
 
-#[derive(Queryable, Debug)]
-pub struct ProductWithCategory {
-    pub product: Product,
-    pub category_title: String,
-}
-
 fn get_all_orders(conn: &mut PgConnection) -> Result<Vec<Value>, DbError> {
     use schema::campaigns::dsl::*;
-    use schema::categories::dsl::*;
     use schema::orders::dsl::*;
-    use schema::products::dsl::*;
 
     let all_orders: HashMap<i32, Order> = orders
         .select((schema::orders::id, Order::as_select()))
@@ -58,45 +58,38 @@ fn get_all_orders(conn: &mut PgConnection) -> Result<Vec<Value>, DbError> {
             schema::orders::discounted_price,
             schema::orders::campaign_id.nullable(),
             schema::orders::user_id,
+            schema::orders::status,
+            schema::orders::created_at,
+            schema::orders::updated_at,
             schema::campaigns::description.nullable(),
         ))
         .load(conn)
         .expect("Orders could not get");
 
-    let all_products: HashMap<i32, Vec<ProductWithCategory>> =
-        OrderToProduct::belonging_to(&order_values)
-            .inner_join(products.inner_join(categories))
-            .select((
-                schema::orders_products::order_id,
-                Product::as_select(),
-                schema::categories::title,
-            ))
-            .load::<(i32, Product, String)>(conn)?
-            .into_iter()
-            .fold(
-                HashMap::new(),
-                |mut acc, (order_id, product, category_title)| {
-                    acc.entry(order_id)
-                        .or_insert_with(Vec::new)
-                        .push(ProductWithCategory {
-                            product,
-                            category_title,
-                        });
-                    acc
-                },
-            );
+    let all_lines: HashMap<i32, Vec<OrderToProduct>> = OrderToProduct::belonging_to(&order_values)
+        .select(OrderToProduct::as_select())
+        .order(schema::orders_products::product_id)
+        .load::<OrderToProduct>(conn)?
+        .into_iter()
+        .fold(HashMap::new(), |mut acc, line| {
+            acc.entry(line.order_id).or_insert_with(Vec::new).push(line);
+            acc
+        });
 
     let mut orders_json = vec![];
 
     for order in order_with_fields {
-        let default_products: Vec<ProductWithCategory> = vec![];
-        let products_for_order = all_products.get(&order.id).unwrap_or(&default_products);
+        let default_lines: Vec<OrderToProduct> = vec![];
+        let lines_for_order = all_lines.get(&order.id).unwrap_or(&default_lines);
         orders_json.push(json!({
             "id": order.id,
             "price_without_discount": order.price_without_discount,
             "discounted_price": order.discounted_price,
             "campaign_id": order.campaign_id,
             "user_id": order.user_id,
+            "status": order.status,
+            "created_at": order.created_at,
+            "updated_at": order.updated_at,
             "campaign": match order.campaign_description {
                 Some(campaign_description) => {
                     json!({
@@ -105,23 +98,22 @@ fn get_all_orders(conn: &mut PgConnection) -> Result<Vec<Value>, DbError> {
                 }
                 None => json!(null),
             },
-            "products": products_for_order.iter().map(|product| {
-                json!({
-                    "id": product.product.id,
-                    "title": product.product.title,
-                    "author": product.product.author,
-                    "list_price": product.product.list_price,
-                    "stock_quantity": product.product.stock_quantity,
-                    "category": {
-                        "title": product.category_title,
-                    },
-                })
-            }).collect::<Vec<_>>()
+            "products": lines_for_order.iter().map(get_order_line_json).collect::<Vec<_>>()
         }));
     }
     Ok(orders_json)
 }
 
+pub fn ensure_user_exists(conn: &mut PgConnection, _user_id: i32) -> Result<(), DbError> {
+    users
+        .filter(id.eq(_user_id))
+        .select(User::as_select())
+        .first::<User>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("User {} not found", _user_id)))?;
+    Ok(())
+}
+
 pub fn get_all_users(conn: &mut PgConnection) -> Result<Vec<User>, DbError> {
     let all_users = users.select(User::as_select()).load(conn).expect("Users could not get");
     Ok(all_users)
@@ -142,6 +134,8 @@ pub fn get_all_users_with_orders(conn: &mut PgConnection) -> Result<Vec<UserWith
             UserWithOrders {
                 id: user.id,
                 username: user.username.clone(),
+                created_at: user.created_at,
+                updated_at: user.updated_at,
                 orders: user_orders,
             }
         })
@@ -176,6 +170,8 @@ pub fn get_user_by_id_with_orders(
     let user_with_orders = UserWithOrders {
         id: user.id,
         username: user.username.clone(),
+        created_at: user.created_at,
+        updated_at: user.updated_at,
         orders: user_orders,
     };
     Ok(user_with_orders)
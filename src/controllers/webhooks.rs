@@ -0,0 +1,671 @@
+use crate::controllers::errors::{self, ApiError};
+use crate::controllers::outbox;
+use crate::controllers::retry::RetryPolicy;
+use crate::insertables::{NewWebhookEvent, NewWebhookSubscription};
+use crate::DeliverWebhook;
+use ::url::{Host, Url};
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder, Result};
+use diesel::{prelude::*, r2d2};
+use hmac::{Hmac, Mac};
+use rust_order_api::models::{
+    WebhookDelivery, WebhookDeliveryStatus, WebhookEvent, WebhookSubscription,
+};
+use rust_order_api::schema;
+use schema::webhook_subscriptions::dsl::*;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::env;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
+
+pub const ORDER_CREATED: &str = "order.created";
+pub const ORDER_CANCELLED: &str = "order.cancelled";
+pub const PRODUCT_STOCK_LOW: &str = "product.stock_low";
+const EVENT_TYPES: [&str; 3] = [ORDER_CREATED, ORDER_CANCELLED, PRODUCT_STOCK_LOW];
+
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_STOCK_LOW_THRESHOLD: i32 = 5;
+
+struct DeliveryAttempt {
+    response_status: Option<i32>,
+    error: Option<String>,
+}
+
+/// Webhooks may only reach loopback, private and link-local addresses when
+/// WEBHOOK_ALLOW_PRIVATE_URLS=true, e.g. to deliver to the local receiver.
+fn allows_private_urls() -> bool {
+    env::var("WEBHOOK_ALLOW_PRIVATE_URLS").is_ok_and(|value| value == "true")
+}
+
+/// Addresses that would let a subscription reach this host or the internal
+/// network instead of a public receiver.
+fn is_blocked_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_unspecified()
+                || address.is_broadcast()
+        }
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(mapped_address) => is_blocked_address(IpAddr::V4(mapped_address)),
+            None => {
+                address.is_loopback()
+                    || address.is_unique_local()
+                    || address.is_unicast_link_local()
+                    || address.is_unspecified()
+            }
+        },
+    }
+}
+
+fn is_blocked_host(host: Host<&str>) -> bool {
+    match host {
+        Host::Domain(domain) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+        Host::Ipv4(address) => is_blocked_address(IpAddr::V4(address)),
+        Host::Ipv6(address) => is_blocked_address(IpAddr::V6(address)),
+    }
+}
+
+/// Resolver for deliveries that refuses hosts resolving to a blocked address.
+/// The connection is made to the addresses checked here, so a name cannot
+/// switch to an internal address between the check and the request.
+fn resolve_public_addresses(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+    if let Some(address) = addresses
+        .iter()
+        .find(|address| is_blocked_address(address.ip()))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} resolves to {}, webhooks must not target loopback, private or link-local addresses",
+                netloc,
+                address.ip()
+            ),
+        ));
+    }
+    Ok(addresses)
+}
+
+fn validate_webhook_subscription(
+    subscription: &NewWebhookSubscription,
+    allow_private_urls: bool,
+) -> Result<(), DbError> {
+    if !subscription.url.starts_with("http://") && !subscription.url.starts_with("https://") {
+        return Err(ApiError::BadRequest(
+            "Webhook url must start with http:// or https://".to_string(),
+        )
+        .into());
+    }
+    let webhook_url = Url::parse(&subscription.url).map_err(|err| {
+        ApiError::BadRequest(format!(
+            "Webhook url {} is invalid: {}",
+            subscription.url, err
+        ))
+    })?;
+    if !allow_private_urls && webhook_url.host().is_none_or(is_blocked_host) {
+        return Err(ApiError::BadRequest(
+            "Webhook url must not target loopback, private or link-local addresses".to_string(),
+        )
+        .into());
+    }
+    if subscription.event_types.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Webhook must subscribe to at least one event".to_string(),
+        )
+        .into());
+    }
+    if let Some(unknown_event_type) = subscription
+        .event_types
+        .iter()
+        .find(|_event_type| !EVENT_TYPES.contains(&_event_type.as_str()))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown event type {}, expected one of {:?}",
+            unknown_event_type, EVENT_TYPES
+        ))
+        .into());
+    }
+    if subscription.secret.trim().is_empty() {
+        return Err(ApiError::BadRequest("Webhook secret must not be empty".to_string()).into());
+    }
+    Ok(())
+}
+
+pub fn get_all_webhook_subscriptions(
+    conn: &mut PgConnection,
+) -> Result<Vec<WebhookSubscription>, DbError> {
+    let all_webhook_subscriptions = webhook_subscriptions
+        .select(WebhookSubscription::as_select())
+        .order(id)
+        .load(conn)?;
+    Ok(all_webhook_subscriptions)
+}
+
+pub fn get_webhook_subscription_by_id(
+    conn: &mut PgConnection,
+    webhook_subscription_id: i32,
+) -> Result<WebhookSubscription, DbError> {
+    let webhook_subscription = webhook_subscriptions
+        .filter(id.eq(webhook_subscription_id))
+        .select(WebhookSubscription::as_select())
+        .first::<WebhookSubscription>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Webhook subscription {} not found",
+                webhook_subscription_id
+            ))
+        })?;
+    Ok(webhook_subscription)
+}
+
+pub fn insert_new_webhook_subscription(
+    conn: &mut PgConnection,
+    new_webhook_subscription: &NewWebhookSubscription,
+) -> Result<WebhookSubscription, DbError> {
+    validate_webhook_subscription(new_webhook_subscription, allows_private_urls())?;
+    let webhook_subscription = diesel::insert_into(webhook_subscriptions)
+        .values(new_webhook_subscription)
+        .returning(WebhookSubscription::as_returning())
+        .get_result(conn)?;
+    Ok(webhook_subscription)
+}
+
+pub fn update_webhook_subscription_by_id(
+    conn: &mut PgConnection,
+    webhook_subscription_id: i32,
+    updated_webhook_subscription: &NewWebhookSubscription,
+) -> Result<WebhookSubscription, DbError> {
+    validate_webhook_subscription(updated_webhook_subscription, allows_private_urls())?;
+    let webhook_subscription =
+        diesel::update(webhook_subscriptions.filter(id.eq(webhook_subscription_id)))
+            .set(updated_webhook_subscription)
+            .returning(WebhookSubscription::as_returning())
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| {
+                ApiError::NotFound(format!(
+                    "Webhook subscription {} not found",
+                    webhook_subscription_id
+                ))
+            })?;
+    Ok(webhook_subscription)
+}
+
+pub fn delete_webhook_subscription_by_id(
+    conn: &mut PgConnection,
+    webhook_subscription_id: i32,
+) -> Result<String, DbError> {
+    let deleted = diesel::delete(webhook_subscriptions.filter(id.eq(webhook_subscription_id)))
+        .execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Webhook subscription {} not found",
+            webhook_subscription_id
+        ))
+        .into());
+    }
+    Ok("Webhook subscription deleted".to_string())
+}
+
+/// The delivery log of a subscription, newest first, with the event each
+/// delivery carried.
+pub fn get_webhook_deliveries_by_subscription_id(
+    conn: &mut PgConnection,
+    webhook_subscription_id: i32,
+) -> Result<Vec<Value>, DbError> {
+    let webhook_subscription = get_webhook_subscription_by_id(conn, webhook_subscription_id)?;
+    let deliveries = WebhookDelivery::belonging_to(&webhook_subscription)
+        .inner_join(schema::webhook_events::table)
+        .select((WebhookDelivery::as_select(), WebhookEvent::as_select()))
+        .order(schema::webhook_deliveries::id.desc())
+        .load::<(WebhookDelivery, WebhookEvent)>(conn)?;
+    Ok(deliveries
+        .into_iter()
+        .map(|(delivery, event)| {
+            json!({
+                "id": delivery.id,
+                "status": delivery.status,
+                "attempts": delivery.attempts,
+                "response_status": delivery.response_status,
+                "last_error": delivery.last_error,
+                "delivered_at": delivery.delivered_at,
+                "created_at": delivery.created_at,
+                "updated_at": delivery.updated_at,
+                "event": get_event_body(&event),
+            })
+        })
+        .collect())
+}
+
+fn get_event_body(event: &WebhookEvent) -> Value {
+    json!({
+        "id": event.id,
+        "type": event.event_type,
+        "created_at": event.created_at,
+        "data": event.payload,
+    })
+}
+
+/// Records an event and queues one delivery for every active subscription to
+/// its type. Nothing is recorded when no active subscription listens to it.
+pub fn publish_event(
+    conn: &mut PgConnection,
+    _event_type: &str,
+    data: &Value,
+) -> Result<(), DbError> {
+    let subscription_ids: Vec<i32> = webhook_subscriptions
+        .filter(is_active.eq(true))
+        .filter(event_types.contains(vec![_event_type]))
+        .select(id)
+        .load(conn)?;
+    if subscription_ids.is_empty() {
+        return Ok(());
+    }
+
+    let event = diesel::insert_into(schema::webhook_events::table)
+        .values(&NewWebhookEvent {
+            event_type: _event_type.to_string(),
+            payload: data.clone(),
+        })
+        .returning(WebhookEvent::as_returning())
+        .get_result(conn)?;
+    for subscription_id in subscription_ids {
+        let delivery_id = diesel::insert_into(schema::webhook_deliveries::table)
+            .values((
+                schema::webhook_deliveries::webhook_subscription_id.eq(subscription_id),
+                schema::webhook_deliveries::webhook_event_id.eq(event.id),
+            ))
+            .returning(schema::webhook_deliveries::id)
+            .get_result::<i32>(conn)?;
+        outbox::enqueue(
+            conn,
+            &DeliverWebhook {
+                webhook_delivery_id: delivery_id,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Publishes `product.stock_low` when a change takes the stock of a product
+/// below STOCK_LOW_THRESHOLD (default 5). Changes that stay below it do not
+/// publish again.
+pub fn publish_stock_change(
+    conn: &mut PgConnection,
+    product_id: i32,
+    previous_stock: i32,
+    new_stock: i32,
+) -> Result<(), DbError> {
+    let threshold = env::var("STOCK_LOW_THRESHOLD")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_STOCK_LOW_THRESHOLD);
+    if previous_stock < threshold || new_stock >= threshold {
+        return Ok(());
+    }
+    let product_title = schema::products::table
+        .filter(schema::products::id.eq(product_id))
+        .select(schema::products::title)
+        .first::<String>(conn)?;
+    publish_event(
+        conn,
+        PRODUCT_STOCK_LOW,
+        &json!({
+            "product_id": product_id,
+            "title": product_title,
+            "stock_quantity": new_stock,
+            "threshold": threshold,
+        }),
+    )
+}
+
+/// Hex encoded HMAC-SHA256 of the request body, keyed with the subscription
+/// secret. Receivers recompute it to check that a request came from us.
+pub fn sign_payload(webhook_secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(webhook_secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn get_pending_delivery(
+    conn: &mut PgConnection,
+    webhook_delivery_id: i32,
+) -> Result<Option<(WebhookDelivery, WebhookSubscription, WebhookEvent)>, DbError> {
+    let delivery = schema::webhook_deliveries::table
+        .inner_join(webhook_subscriptions)
+        .inner_join(schema::webhook_events::table)
+        .filter(schema::webhook_deliveries::id.eq(webhook_delivery_id))
+        .filter(schema::webhook_deliveries::status.eq(WebhookDeliveryStatus::Pending))
+        .select((
+            WebhookDelivery::as_select(),
+            WebhookSubscription::as_select(),
+            WebhookEvent::as_select(),
+        ))
+        .first(conn)
+        .optional()?;
+    Ok(delivery)
+}
+
+fn send_webhook(
+    subscription: &WebhookSubscription,
+    event: &WebhookEvent,
+    webhook_delivery_id: i32,
+) -> DeliveryAttempt {
+    let body = get_event_body(event).to_string();
+    let mut agent_builder = ureq::AgentBuilder::new()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirects(0);
+    if !allows_private_urls() {
+        agent_builder = agent_builder.resolver(resolve_public_addresses);
+    }
+    let agent = agent_builder.build();
+    let response = agent
+        .post(&subscription.url)
+        .set("Content-Type", "application/json")
+        .set(EVENT_HEADER, &event.event_type)
+        .set(DELIVERY_HEADER, &webhook_delivery_id.to_string())
+        .set(
+            SIGNATURE_HEADER,
+            &format!("sha256={}", sign_payload(&subscription.secret, &body)),
+        )
+        .send_string(&body);
+    match response {
+        Ok(response) if (200..300).contains(&response.status()) => DeliveryAttempt {
+            response_status: Some(response.status() as i32),
+            error: None,
+        },
+        Ok(response) | Err(ureq::Error::Status(_, response)) => DeliveryAttempt {
+            response_status: Some(response.status() as i32),
+            error: Some(format!("Receiver responded {}", response.status())),
+        },
+        Err(err) => DeliveryAttempt {
+            response_status: None,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+fn record_attempt(
+    conn: &mut PgConnection,
+    webhook_delivery_id: i32,
+    attempt: &DeliveryAttempt,
+    is_last_attempt: bool,
+) -> Result<(), DbError> {
+    use schema::webhook_deliveries::dsl::*;
+    let next_status = match attempt.error {
+        None => WebhookDeliveryStatus::Succeeded,
+        Some(_) if is_last_attempt => WebhookDeliveryStatus::Failed,
+        Some(_) => WebhookDeliveryStatus::Pending,
+    };
+    diesel::update(webhook_deliveries)
+        .filter(id.eq(webhook_delivery_id))
+        .set((
+            status.eq(next_status),
+            attempts.eq(attempts + 1),
+            response_status.eq(attempt.response_status),
+            last_error.eq(&attempt.error),
+        ))
+        .execute(conn)?;
+    if next_status == WebhookDeliveryStatus::Succeeded {
+        diesel::update(webhook_deliveries)
+            .filter(id.eq(webhook_delivery_id))
+            .set(delivered_at.eq(diesel::dsl::now))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Sends a queued delivery, retrying with backoff as the policy allows. Every
+/// attempt is recorded on the delivery, which ends up `succeeded` or
+/// `failed`. Deliveries that are no longer pending are skipped.
+pub async fn process_webhook_delivery(
+    pool: DbPool,
+    retry_policy: &RetryPolicy,
+    webhook_delivery_id: i32,
+) -> Result<(), DbError> {
+    let load_pool = pool.clone();
+    let delivery = web::block(move || {
+        let mut conn = load_pool.get()?;
+        get_pending_delivery(&mut conn, webhook_delivery_id)
+    })
+    .await??;
+    let Some((_, subscription, event)) = delivery else {
+        return Ok(());
+    };
+
+    let max_attempts = retry_policy.max_attempts;
+    let (result, _) = retry_policy
+        .retry(|_attempts| {
+            let record_pool = pool.clone();
+            let subscription = subscription.clone();
+            let event = event.clone();
+            async move {
+                let _is_active = subscription.is_active;
+                let attempt = if _is_active {
+                    web::block(move || send_webhook(&subscription, &event, webhook_delivery_id))
+                        .await?
+                } else {
+                    DeliveryAttempt {
+                        response_status: None,
+                        error: Some("Webhook subscription is inactive".to_string()),
+                    }
+                };
+                let is_done = attempt.error.is_none() || !_is_active || _attempts >= max_attempts;
+                let _error = attempt.error.clone();
+
+                web::block(move || {
+                    let mut conn = record_pool.get()?;
+                    record_attempt(&mut conn, webhook_delivery_id, &attempt, is_done)
+                })
+                .await??;
+                match _error {
+                    Some(_error) if !is_done => Err(DbError::from(_error)),
+                    _ => Ok(()),
+                }
+            }
+        })
+        .await;
+    result
+}
+
+#[get("/api/webhooks")]
+async fn get_webhooks(pool: web::Data<DbPool>) -> Result<impl Responder> {
+    let all_webhook_subscriptions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_all_webhook_subscriptions(&mut conn)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(all_webhook_subscriptions))
+}
+
+#[get("/api/webhooks/{webhook_id}")]
+async fn get_webhook(
+    pool: web::Data<DbPool>,
+    webhook_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let webhook_subscription = web::block(move || {
+        let mut conn = pool.get()?;
+        get_webhook_subscription_by_id(&mut conn, *webhook_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(webhook_subscription))
+}
+
+#[post("/api/webhooks")]
+async fn create_webhook(
+    pool: web::Data<DbPool>,
+    form: web::Json<NewWebhookSubscription>,
+) -> Result<impl Responder> {
+    let webhook_subscription = web::block(move || {
+        let mut conn = pool.get()?;
+        insert_new_webhook_subscription(&mut conn, &form)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Created().json(webhook_subscription))
+}
+
+#[put("/api/webhooks/{webhook_id}")]
+async fn update_webhook(
+    pool: web::Data<DbPool>,
+    webhook_id: web::Path<i32>,
+    form: web::Json<NewWebhookSubscription>,
+) -> Result<impl Responder> {
+    let webhook_subscription = web::block(move || {
+        let mut conn = pool.get()?;
+        update_webhook_subscription_by_id(&mut conn, *webhook_id, &form)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(webhook_subscription))
+}
+
+#[delete("/api/webhooks/{webhook_id}")]
+async fn delete_webhook(
+    pool: web::Data<DbPool>,
+    webhook_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let message = web::block(move || {
+        let mut conn = pool.get()?;
+        delete_webhook_subscription_by_id(&mut conn, *webhook_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(message))
+}
+
+#[get("/api/webhooks/{webhook_id}/deliveries")]
+async fn get_webhook_deliveries(
+    pool: web::Data<DbPool>,
+    webhook_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let deliveries = web::block(move || {
+        let mut conn = pool.get()?;
+        get_webhook_deliveries_by_subscription_id(&mut conn, *webhook_id)
+    })
+    .await?
+    .map_err(errors::into_response_error)?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_is_signed_with_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign_payload("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            sign_payload("key", "The quick brown fox jumps over the lazy dog"),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn signature_depends_on_the_secret_and_the_body() {
+        let body = r#"{"type":"order.created","order_id":1}"#;
+        let signature = sign_payload("secret", body);
+        assert_eq!(signature, sign_payload("secret", body));
+        assert_ne!(signature, sign_payload("other secret", body));
+        assert_ne!(
+            signature,
+            sign_payload("secret", r#"{"type":"order.created","order_id":2}"#)
+        );
+    }
+
+    fn subscription(webhook_url: &str, webhook_secret: &str) -> NewWebhookSubscription {
+        NewWebhookSubscription {
+            url: webhook_url.to_string(),
+            event_types: vec![ORDER_CREATED.to_string()],
+            secret: webhook_secret.to_string(),
+            is_active: true,
+        }
+    }
+
+    fn is_bad_request(result: Result<(), DbError>) -> bool {
+        result.is_err_and(|err| matches!(err.downcast_ref(), Some(ApiError::BadRequest(_))))
+    }
+
+    #[test]
+    fn empty_secret_is_rejected() {
+        let webhook_url = "https://example.com/hooks";
+        assert!(is_bad_request(validate_webhook_subscription(
+            &subscription(webhook_url, ""),
+            false
+        )));
+        assert!(is_bad_request(validate_webhook_subscription(
+            &subscription(webhook_url, "   "),
+            false
+        )));
+        assert!(validate_webhook_subscription(&subscription(webhook_url, "s3cret"), false).is_ok());
+    }
+
+    #[test]
+    fn internal_urls_are_rejected() {
+        for webhook_url in [
+            "http://localhost:3001/hooks",
+            "http://api.localhost/hooks",
+            "http://127.0.0.1/hooks",
+            "http://10.0.0.5/hooks",
+            "http://172.16.0.1/hooks",
+            "http://192.168.1.1/hooks",
+            "http://169.254.169.254/latest/meta-data",
+            "http://0.0.0.0/hooks",
+            "http://[::1]/hooks",
+            "http://[fd00::1]/hooks",
+            "http://[fe80::1]/hooks",
+            "http://[::ffff:127.0.0.1]/hooks",
+        ] {
+            assert!(
+                is_bad_request(validate_webhook_subscription(
+                    &subscription(webhook_url, "s3cret"),
+                    false
+                )),
+                "{} was accepted",
+                webhook_url
+            );
+            assert!(
+                validate_webhook_subscription(&subscription(webhook_url, "s3cret"), true).is_ok()
+            );
+        }
+        for webhook_url in [
+            "https://example.com/hooks",
+            "http://93.184.215.14:8080/hooks",
+        ] {
+            assert!(
+                validate_webhook_subscription(&subscription(webhook_url, "s3cret"), false).is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn internal_addresses_are_not_resolved() {
+        assert!(resolve_public_addresses("127.0.0.1:3001").is_err());
+        assert!(resolve_public_addresses("[::1]:3001").is_err());
+        assert!(resolve_public_addresses("10.1.2.3:443").is_err());
+        assert_eq!(
+            resolve_public_addresses("93.184.215.14:443").unwrap(),
+            vec!["93.184.215.14:443".parse().unwrap()]
+        );
+    }
+}
@@ -1,6 +1,12 @@
-use diesel::Insertable;
-use rust_order_api::schema::{campaigns, orders, products, users};
+use diesel::{AsChangeset, Insertable};
+use rust_order_api::models::{Money, OrderStatus, PaymentStatus};
+use rust_order_api::schema::{
+    addresses, campaigns, cart_items, dead_letter_jobs, notifications, order_jobs, orders,
+    orders_products, outbox_messages, payments, products, shipping_methods, users, webhook_events,
+    webhook_subscriptions,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Insertable, Serialize, Deserialize, Clone)]
 #[diesel(table_name=users)]
@@ -14,15 +20,17 @@ pub struct NewProduct {
     pub title: String,
     pub category_id: i32,
     pub author: String,
-    pub list_price: f64,
+    pub list_price: Money,
     pub stock_quantity: i32,
+    #[serde(default)]
+    pub weight_grams: i32,
 }
 
 #[derive(Insertable, Serialize, Deserialize, Clone)]
 #[diesel(table_name=campaigns)]
 pub struct NewCampaign {
     pub description: String,
-    pub min_purchase_price: f64,
+    pub min_purchase_price: Money,
     pub min_purchase_quantity: i32,
     pub discount_quantity: i32,
     pub discount_percent: i32,
@@ -33,8 +41,132 @@ pub struct NewCampaign {
 #[derive(Insertable, Serialize, Deserialize, Clone)]
 #[diesel(table_name=orders)]
 pub struct NewOrder {
-    pub price_without_discount: f64,
-    pub discounted_price: f64,
+    pub price_without_discount: Money,
+    pub discounted_price: Money,
     pub campaign_id: Option<i32>,
     pub user_id: i32,
+    pub subtotal: Money,
+    pub shipping_fee: Money,
+    pub discount_amount: Money,
+    pub pricing_rules: Value,
+    pub shipping_method: String,
+    pub shipping_address: Option<Value>,
+    pub billing_address: Option<Value>,
+    pub status: OrderStatus,
+    pub risk_flags: Option<Value>,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone, Debug)]
+#[diesel(table_name=orders_products)]
+pub struct NewOrderLine {
+    pub order_id: i32,
+    pub product_id: i32,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub title: String,
+    pub author: String,
+    pub category_title: String,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=notifications)]
+pub struct NewNotification {
+    pub user_id: i32,
+    pub order_id: Option<i32>,
+    pub kind: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=cart_items)]
+pub struct NewCartItem {
+    pub user_id: i32,
+    pub product_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Insertable, AsChangeset, Serialize, Deserialize, Clone)]
+#[diesel(table_name=shipping_methods)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewShippingMethod {
+    pub code: String,
+    pub name: String,
+    pub base_fee: Money,
+    #[serde(default)]
+    pub per_item_fee: Money,
+    #[serde(default)]
+    pub per_kg_fee: Money,
+    pub free_shipping_threshold: Option<Money>,
+    #[serde(default = "default_is_active")]
+    pub is_active: bool,
+}
+
+fn default_is_active() -> bool {
+    true
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=addresses)]
+pub struct NewAddress {
+    pub user_id: i32,
+    pub full_name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub state: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+    pub phone: Option<String>,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=dead_letter_jobs)]
+pub struct NewDeadLetterJob {
+    pub job_name: String,
+    pub job_id: String,
+    pub payload: Value,
+    pub error: String,
+    pub attempts: i32,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=order_jobs)]
+pub struct NewOrderJob {
+    pub payload: Value,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=outbox_messages)]
+pub struct NewOutboxMessage {
+    pub job_name: String,
+    pub payload: Value,
+}
+
+#[derive(Insertable, AsChangeset, Serialize, Deserialize, Clone)]
+#[diesel(table_name=webhook_subscriptions)]
+pub struct NewWebhookSubscription {
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub secret: String,
+    #[serde(default = "default_is_active")]
+    pub is_active: bool,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=webhook_events)]
+pub struct NewWebhookEvent {
+    pub event_type: String,
+    pub payload: Value,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Clone)]
+#[diesel(table_name=payments)]
+pub struct NewPayment {
+    pub order_id: i32,
+    pub gateway: String,
+    pub payment_method: Option<String>,
+    pub reference: String,
+    pub status: PaymentStatus,
+    pub amount: Money,
 }
@@ -1,32 +1,70 @@
 mod controllers {
+    pub mod addresses;
     pub mod campaigns;
+    pub mod carts;
+    pub mod errors;
+    pub mod fulfilment;
     pub mod functions;
+    pub mod idempotency;
+    pub mod notifications;
+    pub mod order_edits;
+    pub mod order_events;
+    pub mod order_jobs;
     pub mod orders;
+    pub mod outbox;
+    pub mod payments;
     pub mod products;
+    pub mod quotes;
+    pub mod reorders;
+    pub mod retry;
+    pub mod returns;
+    pub mod risk;
+    pub mod shipping;
     pub mod users;
+    pub mod webhooks;
 }
 mod insertables;
 use actix_web::{web, App, HttpServer};
+use apalis::layers::{Extension, TraceLayer};
 use apalis::prelude::*;
-use apalis::{layers::TraceLayer, redis::RedisStorage};
+use apalis::redis::RedisStorage;
+use controllers::addresses;
 use controllers::campaigns;
+use controllers::carts;
+use controllers::fulfilment;
+use controllers::notifications;
+use controllers::order_edits;
+use controllers::order_events::{self, OrderEventHub};
+use controllers::order_jobs;
 use controllers::orders;
+use controllers::outbox::OutboxRelay;
+use controllers::payments::{MockPaymentGateway, PaymentGateway};
 use controllers::products;
+use controllers::quotes;
+use controllers::reorders;
+use controllers::retry::RetryPolicy;
+use controllers::returns;
+use controllers::shipping;
 use controllers::users;
+use controllers::webhooks;
 use diesel::{r2d2, PgConnection};
 use dotenvy::dotenv;
 use futures::future;
+use rust_order_api::models::Money;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::Arc;
+use tracing_subscriber::EnvFilter;
+type DbError = Box<dyn std::error::Error + Send + Sync>;
 type DbPool = r2d2::Pool<r2d2::ConnectionManager<PgConnection>>;
 use r2d2_redis::{r2d2 as redis_r2d2, RedisConnectionManager};
 type RedisPool = redis_r2d2::Pool<RedisConnectionManager>;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct QueryOrder {
     pub id: i32,
-    pub price_without_discount: f64,
-    pub discounted_price: f64,
+    pub price_without_discount: Money,
+    pub discounted_price: Money,
     pub campaign_id: Option<i32>,
     pub user_id: i32,
 }
@@ -35,29 +73,108 @@ impl Job for QueryOrder {
     const NAME: &'static str = "apalis::QueryOrder";
 }
 
-async fn order_service(_job: QueryOrder, _ctx: JobContext) {}
+/// An order placed with `POST /api/orders?async=true`, waiting to be priced
+/// and inserted. The request itself is stored on the order job.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SubmitOrder {
+    pub order_job_id: i32,
+}
+
+impl Job for SubmitOrder {
+    const NAME: &'static str = "apalis::SubmitOrder";
+}
+
+/// A webhook delivery queued by `webhooks::publish_event`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DeliverWebhook {
+    pub webhook_delivery_id: i32,
+}
+
+impl Job for DeliverWebhook {
+    const NAME: &'static str = "apalis::DeliverWebhook";
+}
+
+async fn order_service(job: QueryOrder, ctx: JobContext) -> Result<(), DbError> {
+    let pool = ctx.data::<DbPool>()?.clone();
+    let retry_policy = ctx.data::<RetryPolicy>()?.clone();
+    order_jobs::process_order_job(pool, &retry_policy, job, ctx.id().to_string()).await
+}
+
+async fn submit_order_service(job: SubmitOrder, ctx: JobContext) -> Result<(), DbError> {
+    let pool = ctx.data::<DbPool>()?.clone();
+    let redis_pool = ctx.data::<RedisPool>()?.clone();
+    let gateway = ctx.data::<Arc<dyn PaymentGateway>>()?.clone();
+    let retry_policy = ctx.data::<RetryPolicy>()?.clone();
+    order_jobs::process_submit_order(
+        pool,
+        redis_pool,
+        gateway,
+        &retry_policy,
+        job,
+        ctx.id().to_string(),
+    )
+    .await
+}
+
+async fn deliver_webhook_service(job: DeliverWebhook, ctx: JobContext) -> Result<(), DbError> {
+    let pool = ctx.data::<DbPool>()?.clone();
+    let retry_policy = ctx.data::<RetryPolicy>()?.clone();
+    webhooks::process_webhook_delivery(pool, &retry_policy, job.webhook_delivery_id).await
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
     let db_pool = initialize_db_pool();
     let redis_pool = initialize_redis_pool();
+    let payment_gateway = initialize_payment_gateway();
     let storage = RedisStorage::connect("redis://127.0.0.1/")
         .await
         .expect("Redis storage error");
-    let storage_data = web::Data::new(storage.clone());
+    let submit_storage = RedisStorage::<SubmitOrder>::connect("redis://127.0.0.1/")
+        .await
+        .expect("Redis storage error");
+    let webhook_storage = RedisStorage::<DeliverWebhook>::connect("redis://127.0.0.1/")
+        .await
+        .expect("Redis storage error");
+    let order_event_hub = OrderEventHub::new();
+    let outbox_relay = OutboxRelay::new(
+        db_pool.clone(),
+        storage.clone(),
+        submit_storage.clone(),
+        webhook_storage.clone(),
+        order_event_hub.clone(),
+        payment_gateway.clone(),
+    );
+    let worker_db_pool = db_pool.clone();
+    let submit_worker_db_pool = db_pool.clone();
+    let submit_worker_redis_pool = redis_pool.clone();
+    let submit_worker_payment_gateway = payment_gateway.clone();
+    let sweeper_db_pool = db_pool.clone();
+    let webhook_worker_db_pool = db_pool.clone();
+    let retry_policy = RetryPolicy::from_env("ORDER_JOB");
+    let submit_retry_policy = retry_policy.clone();
+    let webhook_retry_policy = RetryPolicy::from_env("WEBHOOK");
     let http = async {
         HttpServer::new(move || {
             App::new()
                 .app_data(web::Data::new(db_pool.clone()))
                 .app_data(web::Data::new(redis_pool.clone()))
-                .app_data(storage_data.clone())
+                .app_data(web::Data::from(payment_gateway.clone()))
+                .app_data(web::Data::new(order_event_hub.clone()))
                 .service(users::get_users)
                 .service(users::get_users_with_orders)
                 .service(users::get_user)
                 .service(users::get_user_with_orders)
                 .service(users::create_user)
                 .service(users::delete_user)
+                .service(fulfilment::get_stats)
+                .service(notifications::get_notifications)
                 .service(products::get_products)
                 .service(products::get_product)
                 .service(products::create_product)
@@ -69,7 +186,43 @@ async fn main() -> std::io::Result<()> {
                 .service(orders::get_orders)
                 .service(orders::get_order)
                 .service(orders::create_order)
+                .service(orders::update_order_status)
+                .service(orders::cancel_order)
                 .service(orders::delete_order)
+                .service(order_edits::add_item)
+                .service(order_edits::remove_item)
+                .service(order_events::stream_order_events)
+                .service(reorders::reorder)
+                .service(quotes::create_quote)
+                .service(carts::get_cart)
+                .service(carts::add_to_cart)
+                .service(carts::update_cart)
+                .service(carts::remove_from_cart)
+                .service(carts::checkout_cart)
+                .service(shipping::get_shipping_methods)
+                .service(shipping::create_shipping_method)
+                .service(shipping::update_shipping_method)
+                .service(addresses::get_addresses)
+                .service(addresses::get_address)
+                .service(addresses::create_address)
+                .service(addresses::update_address)
+                .service(addresses::delete_address)
+                .service(returns::create_return)
+                .service(returns::get_order_returns)
+                .service(returns::get_return)
+                .service(returns::approve_return)
+                .service(returns::reject_return)
+                .service(order_jobs::get_order_job)
+                .service(order_jobs::get_dead_letter_jobs)
+                .service(order_jobs::get_dead_letter_job)
+                .service(order_jobs::retry_dead_letter)
+                .service(order_jobs::discard_dead_letter)
+                .service(webhooks::get_webhooks)
+                .service(webhooks::get_webhook)
+                .service(webhooks::create_webhook)
+                .service(webhooks::update_webhook)
+                .service(webhooks::delete_webhook)
+                .service(webhooks::get_webhook_deliveries)
         })
         .bind((
             "127.0.0.1",
@@ -86,12 +239,38 @@ async fn main() -> std::io::Result<()> {
         .register_with_count(2, move |index| {
             WorkerBuilder::new(format!("order-queue-{index}"))
                 .layer(TraceLayer::new())
+                .layer(Extension(worker_db_pool.clone()))
+                .layer(Extension(retry_policy.clone()))
                 .with_storage(storage.clone())
                 .build_fn(order_service)
         })
+        .register_with_count(2, move |index| {
+            WorkerBuilder::new(format!("submit-order-queue-{index}"))
+                .layer(TraceLayer::new())
+                .layer(Extension(submit_worker_db_pool.clone()))
+                .layer(Extension(submit_worker_redis_pool.clone()))
+                .layer(Extension(submit_worker_payment_gateway.clone()))
+                .layer(Extension(submit_retry_policy.clone()))
+                .with_storage(submit_storage.clone())
+                .build_fn(submit_order_service)
+        })
+        .register_with_count(2, move |index| {
+            WorkerBuilder::new(format!("webhook-queue-{index}"))
+                .layer(TraceLayer::new())
+                .layer(Extension(webhook_worker_db_pool.clone()))
+                .layer(Extension(webhook_retry_policy.clone()))
+                .with_storage(webhook_storage.clone())
+                .build_fn(deliver_webhook_service)
+        })
         .run();
 
-    future::try_join(http, worker).await?;
+    future::try_join4(
+        http,
+        worker,
+        outbox_relay.run(),
+        order_jobs::run_order_job_sweeper(sweeper_db_pool),
+    )
+    .await?;
     Ok(())
 }
 
@@ -107,3 +286,11 @@ fn initialize_redis_pool() -> RedisPool {
         .build(manager)
         .expect("Redis pool error")
 }
+
+/// Picks the gateway named by PAYMENT_GATEWAY, the mock one by default.
+fn initialize_payment_gateway() -> Arc<dyn PaymentGateway> {
+    match env::var("PAYMENT_GATEWAY").as_deref() {
+        Ok("mock") | Err(_) => Arc::new(MockPaymentGateway),
+        Ok(gateway) => panic!("Unknown payment gateway {}", gateway),
+    }
+}
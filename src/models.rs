@@ -1,12 +1,30 @@
 use diesel::prelude::*;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::io::Write;
 use crate::schema::{users, categories, products, orders, campaigns, orders_products};
+use crate::schema::{addresses, cart_items, order_status_history, shipping_methods};
+use crate::schema::{order_return_items, order_returns};
+use crate::schema::{dead_letter_jobs, notifications, order_jobs, user_order_stats};
+use crate::schema::{outbox_messages, payments};
+use crate::schema::{webhook_deliveries, webhook_events, webhook_subscriptions};
+
+pub type Money = Decimal;
 
 #[derive(Serialize, Queryable, Selectable, Insertable, Identifiable, Debug, PartialEq)]
 #[diesel(table_name = users)]
 pub struct User {
     pub id: i32,
     pub username: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Serialize, Queryable, Selectable, Identifiable, Debug, PartialEq)]
@@ -14,6 +32,8 @@ pub struct User {
 pub struct Category {
     pub id: i32,
     pub title: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, PartialEq)]
@@ -24,8 +44,11 @@ pub struct Product {
     pub title: String,
     pub category_id: i32,
     pub author: String,
-    pub list_price: f64,
+    pub list_price: Money,
     pub stock_quantity: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub weight_grams: i32,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Selectable, Identifiable, Debug, PartialEq)]
@@ -33,12 +56,14 @@ pub struct Product {
 pub struct Campaign {
     pub id: i32,
     pub description: String,
-    pub min_purchase_price: Option<f64>,
+    pub min_purchase_price: Option<Money>,
     pub min_purchase_quantity: Option<i32>,
     pub discount_quantity: Option<i32>,
     pub discount_percent: Option<i32>,
     pub rule_author: Option<String>,
     pub rule_category: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Selectable, Identifiable, Insertable, Associations, Debug, Clone, PartialEq)]
@@ -47,11 +72,113 @@ pub struct Campaign {
 #[diesel(table_name = orders)]
 pub struct Order {
     pub id: i32,
-    pub price_without_discount: f64,
-    pub discounted_price: f64,
+    pub price_without_discount: Money,
+    pub discounted_price: Money,
     pub campaign_id: Option<i32>,
     pub user_id: i32,
+    pub status: OrderStatus,
+    pub cancelled_by: Option<String>,
+    pub cancellation_reason: Option<String>,
+    pub cancelled_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub subtotal: Money,
+    pub shipping_fee: Money,
+    pub discount_amount: Money,
+    pub pricing_rules: Value,
+    pub shipping_method: String,
+    pub shipping_address: Option<Value>,
+    pub billing_address: Option<Value>,
+    pub risk_flags: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Processing,
+    Review,
+    Pending,
+    Paid,
+    Packed,
+    Shipped,
+    Delivered,
+    Cancelled,
+    Refunded,
+}
+
+pub const ORDER_STATUS_TRANSITIONS: &[(OrderStatus, OrderStatus)] = &[
+    (OrderStatus::Processing, OrderStatus::Pending),
+    (OrderStatus::Processing, OrderStatus::Review),
+    (OrderStatus::Processing, OrderStatus::Cancelled),
+    (OrderStatus::Review, OrderStatus::Pending),
+    (OrderStatus::Review, OrderStatus::Cancelled),
+    (OrderStatus::Pending, OrderStatus::Paid),
+    (OrderStatus::Pending, OrderStatus::Review),
+    (OrderStatus::Pending, OrderStatus::Cancelled),
+    (OrderStatus::Paid, OrderStatus::Packed),
+    (OrderStatus::Paid, OrderStatus::Cancelled),
+    (OrderStatus::Paid, OrderStatus::Refunded),
+    (OrderStatus::Packed, OrderStatus::Shipped),
+    (OrderStatus::Packed, OrderStatus::Cancelled),
+    (OrderStatus::Packed, OrderStatus::Refunded),
+    (OrderStatus::Shipped, OrderStatus::Delivered),
+    (OrderStatus::Delivered, OrderStatus::Refunded),
+];
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Processing => "processing",
+            OrderStatus::Review => "review",
+            OrderStatus::Pending => "pending",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Packed => "packed",
+            OrderStatus::Shipped => "shipped",
+            OrderStatus::Delivered => "delivered",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+
+    pub fn can_transition_to(&self, next_status: OrderStatus) -> bool {
+        ORDER_STATUS_TRANSITIONS.contains(&(*self, next_status))
+    }
+}
 
+impl ToSql<Text, Pg> for OrderStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for OrderStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"processing" => Ok(OrderStatus::Processing),
+            b"review" => Ok(OrderStatus::Review),
+            b"pending" => Ok(OrderStatus::Pending),
+            b"paid" => Ok(OrderStatus::Paid),
+            b"packed" => Ok(OrderStatus::Packed),
+            b"shipped" => Ok(OrderStatus::Shipped),
+            b"delivered" => Ok(OrderStatus::Delivered),
+            b"cancelled" => Ok(OrderStatus::Cancelled),
+            b"refunded" => Ok(OrderStatus::Refunded),
+            _ => Err("Unrecognized order status".into()),
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug)]
+#[diesel(belongs_to(Order))]
+#[diesel(table_name = order_status_history)]
+pub struct OrderStatusHistory {
+    pub id: i32,
+    pub order_id: i32,
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub changed_at: NaiveDateTime,
 }
 
 #[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug)]
@@ -62,4 +189,471 @@ pub struct Order {
 pub struct OrderToProduct {
     pub order_id: i32,
     pub product_id: i32,
+    pub quantity: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub unit_price: Money,
+    pub title: String,
+    pub author: String,
+    pub category_title: String,
+    pub returned_quantity: i32,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug)]
+#[diesel(belongs_to(User))]
+#[diesel(belongs_to(Product))]
+#[diesel(table_name = cart_items)]
+#[diesel(primary_key(user_id, product_id))]
+pub struct CartItem {
+    pub user_id: i32,
+    pub product_id: i32,
+    pub quantity: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Selectable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(table_name = shipping_methods)]
+pub struct ShippingMethod {
+    pub id: i32,
+    pub code: String,
+    pub name: String,
+    pub base_fee: Money,
+    pub per_item_fee: Money,
+    pub per_kg_fee: Money,
+    pub free_shipping_threshold: Option<Money>,
+    pub is_active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone, PartialEq)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = addresses)]
+pub struct Address {
+    pub id: i32,
+    pub user_id: i32,
+    pub full_name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub state: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+    pub phone: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ReturnStatus {
+    Requested,
+    Approved,
+    Rejected,
+}
+
+impl ReturnStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnStatus::Requested => "requested",
+            ReturnStatus::Approved => "approved",
+            ReturnStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for ReturnStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for ReturnStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"requested" => Ok(ReturnStatus::Requested),
+            b"approved" => Ok(ReturnStatus::Approved),
+            b"rejected" => Ok(ReturnStatus::Rejected),
+            _ => Err("Unrecognized return status".into()),
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(belongs_to(Order))]
+#[diesel(table_name = order_returns)]
+pub struct OrderReturn {
+    pub id: i32,
+    pub order_id: i32,
+    pub status: ReturnStatus,
+    pub reason: Option<String>,
+    pub resolution_note: Option<String>,
+    pub refund_amount: Option<Money>,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(belongs_to(OrderReturn))]
+#[diesel(belongs_to(Product))]
+#[diesel(table_name = order_return_items)]
+#[diesel(primary_key(order_return_id, product_id))]
+pub struct OrderReturnItem {
+    pub order_return_id: i32,
+    pub product_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = user_order_stats)]
+#[diesel(primary_key(user_id))]
+pub struct UserOrderStats {
+    pub user_id: i32,
+    pub order_count: i32,
+    pub total_spent: Money,
+    pub last_order_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = notifications)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: i32,
+    pub order_id: Option<i32>,
+    pub kind: String,
+    pub subject: String,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum DeadLetterStatus {
+    Dead,
+    Retried,
+    Discarded,
+}
+
+impl DeadLetterStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeadLetterStatus::Dead => "dead",
+            DeadLetterStatus::Retried => "retried",
+            DeadLetterStatus::Discarded => "discarded",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for DeadLetterStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for DeadLetterStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"dead" => Ok(DeadLetterStatus::Dead),
+            b"retried" => Ok(DeadLetterStatus::Retried),
+            b"discarded" => Ok(DeadLetterStatus::Discarded),
+            _ => Err("Unrecognized dead letter status".into()),
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = dead_letter_jobs)]
+pub struct DeadLetterJob {
+    pub id: i32,
+    pub job_name: String,
+    pub job_id: String,
+    pub payload: Value,
+    pub error: String,
+    pub attempts: i32,
+    pub status: DeadLetterStatus,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderJobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl OrderJobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderJobStatus::Queued => "queued",
+            OrderJobStatus::Running => "running",
+            OrderJobStatus::Succeeded => "succeeded",
+            OrderJobStatus::Failed => "failed",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for OrderJobStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for OrderJobStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"queued" => Ok(OrderJobStatus::Queued),
+            b"running" => Ok(OrderJobStatus::Running),
+            b"succeeded" => Ok(OrderJobStatus::Succeeded),
+            b"failed" => Ok(OrderJobStatus::Failed),
+            _ => Err("Unrecognized order job status".into()),
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(belongs_to(Order))]
+#[diesel(table_name = order_jobs)]
+pub struct OrderJob {
+    pub id: i32,
+    pub payload: Value,
+    pub status: OrderJobStatus,
+    pub order_id: Option<i32>,
+    pub error: Option<Value>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = outbox_messages)]
+pub struct OutboxMessage {
+    pub id: i32,
+    pub job_name: String,
+    pub payload: Value,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub locked_until: Option<NaiveDateTime>,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = webhook_subscriptions)]
+pub struct WebhookSubscription {
+    pub id: i32,
+    pub url: String,
+    pub event_types: Vec<String>,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub is_active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = webhook_events)]
+pub struct WebhookEvent {
+    pub id: i32,
+    pub event_type: String,
+    pub payload: Value,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl WebhookDeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookDeliveryStatus::Pending => "pending",
+            WebhookDeliveryStatus::Succeeded => "succeeded",
+            WebhookDeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for WebhookDeliveryStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for WebhookDeliveryStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"pending" => Ok(WebhookDeliveryStatus::Pending),
+            b"succeeded" => Ok(WebhookDeliveryStatus::Succeeded),
+            b"failed" => Ok(WebhookDeliveryStatus::Failed),
+            _ => Err("Unrecognized webhook delivery status".into()),
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(belongs_to(WebhookSubscription))]
+#[diesel(belongs_to(WebhookEvent))]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_subscription_id: i32,
+    pub webhook_event_id: i32,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentStatus {
+    Authorized,
+    Captured,
+    Voided,
+    Refunded,
+}
+
+impl PaymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentStatus::Authorized => "authorized",
+            PaymentStatus::Captured => "captured",
+            PaymentStatus::Voided => "voided",
+            PaymentStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for PaymentStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for PaymentStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"authorized" => Ok(PaymentStatus::Authorized),
+            b"captured" => Ok(PaymentStatus::Captured),
+            b"voided" => Ok(PaymentStatus::Voided),
+            b"refunded" => Ok(PaymentStatus::Refunded),
+            _ => Err("Unrecognized payment status".into()),
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(belongs_to(Order))]
+#[diesel(table_name = payments)]
+pub struct Payment {
+    pub id: i32,
+    pub order_id: i32,
+    pub gateway: String,
+    #[serde(skip_serializing)]
+    pub payment_method: Option<String>,
+    pub reference: String,
+    pub status: PaymentStatus,
+    pub amount: Money,
+    pub captured_amount: Money,
+    pub refunded_amount: Money,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_STATUSES: [OrderStatus; 9] = [
+        OrderStatus::Processing,
+        OrderStatus::Review,
+        OrderStatus::Pending,
+        OrderStatus::Paid,
+        OrderStatus::Packed,
+        OrderStatus::Shipped,
+        OrderStatus::Delivered,
+        OrderStatus::Cancelled,
+        OrderStatus::Refunded,
+    ];
+
+    #[test]
+    fn orders_move_forward_through_fulfilment() {
+        assert!(OrderStatus::Processing.can_transition_to(OrderStatus::Pending));
+        assert!(OrderStatus::Pending.can_transition_to(OrderStatus::Paid));
+        assert!(OrderStatus::Paid.can_transition_to(OrderStatus::Packed));
+        assert!(OrderStatus::Packed.can_transition_to(OrderStatus::Shipped));
+        assert!(OrderStatus::Shipped.can_transition_to(OrderStatus::Delivered));
+        assert!(OrderStatus::Delivered.can_transition_to(OrderStatus::Refunded));
+    }
+
+    #[test]
+    fn orders_cannot_skip_or_go_back() {
+        assert!(!OrderStatus::Pending.can_transition_to(OrderStatus::Shipped));
+        assert!(!OrderStatus::Paid.can_transition_to(OrderStatus::Pending));
+        assert!(!OrderStatus::Shipped.can_transition_to(OrderStatus::Cancelled));
+        assert!(!OrderStatus::Delivered.can_transition_to(OrderStatus::Cancelled));
+        assert!(!OrderStatus::Pending.can_transition_to(OrderStatus::Refunded));
+    }
+
+    #[test]
+    fn cancelled_and_refunded_orders_are_final() {
+        for next_status in ALL_STATUSES {
+            assert!(!OrderStatus::Cancelled.can_transition_to(next_status));
+            assert!(!OrderStatus::Refunded.can_transition_to(next_status));
+        }
+    }
+
+    #[test]
+    fn transitions_are_unique_and_never_to_the_same_status() {
+        for (index, (from, to)) in ORDER_STATUS_TRANSITIONS.iter().enumerate() {
+            assert_ne!(from, to);
+            assert!(!ORDER_STATUS_TRANSITIONS[index + 1..].contains(&(*from, *to)));
+        }
+    }
+
+    #[test]
+    fn only_new_and_held_orders_go_to_review() {
+        for from in ALL_STATUSES {
+            let expected = matches!(from, OrderStatus::Processing | OrderStatus::Pending);
+            assert_eq!(from.can_transition_to(OrderStatus::Review), expected);
+        }
+    }
+
+    #[test]
+    fn statuses_round_trip_through_their_names() {
+        for status in ALL_STATUSES {
+            let name = serde_json::to_string(&status).unwrap();
+            assert_eq!(name, format!("\"{}\"", status.as_str()));
+            assert_eq!(serde_json::from_str::<OrderStatus>(&name).unwrap(), status);
+        }
+    }
 }
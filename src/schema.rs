@@ -1,15 +1,44 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    addresses (id) {
+        id -> Int4,
+        user_id -> Int4,
+        full_name -> Varchar,
+        line1 -> Varchar,
+        line2 -> Nullable<Varchar>,
+        city -> Varchar,
+        state -> Nullable<Varchar>,
+        postal_code -> Varchar,
+        country -> Varchar,
+        phone -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     campaigns (id) {
         id -> Int4,
         description -> Varchar,
-        min_purchase_price -> Nullable<Float8>,
+        min_purchase_price -> Nullable<Numeric>,
         min_purchase_quantity -> Nullable<Int4>,
         discount_quantity -> Nullable<Int4>,
         discount_percent -> Nullable<Int4>,
         rule_author -> Nullable<Varchar>,
         rule_category -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    cart_items (user_id, product_id) {
+        user_id -> Int4,
+        product_id -> Int4,
+        quantity -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -17,16 +46,115 @@ diesel::table! {
     categories (id) {
         id -> Int4,
         title -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
 diesel::table! {
     orders (id) {
         id -> Int4,
-        price_without_discount -> Float8,
-        discounted_price -> Float8,
+        price_without_discount -> Numeric,
+        discounted_price -> Numeric,
         campaign_id -> Nullable<Int4>,
         user_id -> Int4,
+        status -> Varchar,
+        cancelled_by -> Nullable<Varchar>,
+        cancellation_reason -> Nullable<Varchar>,
+        cancelled_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        subtotal -> Numeric,
+        shipping_fee -> Numeric,
+        discount_amount -> Numeric,
+        pricing_rules -> Jsonb,
+        shipping_method -> Varchar,
+        shipping_address -> Nullable<Jsonb>,
+        billing_address -> Nullable<Jsonb>,
+        risk_flags -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    dead_letter_jobs (id) {
+        id -> Int4,
+        job_name -> Varchar,
+        job_id -> Varchar,
+        payload -> Jsonb,
+        error -> Text,
+        attempts -> Int4,
+        status -> Varchar,
+        resolved_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (user_id, idempotency_key) {
+        user_id -> Int4,
+        idempotency_key -> Varchar,
+        fingerprint -> Varchar,
+        response -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Int4,
+        user_id -> Int4,
+        order_id -> Nullable<Int4>,
+        kind -> Varchar,
+        subject -> Varchar,
+        body -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_jobs (id) {
+        id -> Int4,
+        payload -> Jsonb,
+        status -> Varchar,
+        order_id -> Nullable<Int4>,
+        error -> Nullable<Jsonb>,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_return_items (order_return_id, product_id) {
+        order_return_id -> Int4,
+        product_id -> Int4,
+        quantity -> Int4,
+    }
+}
+
+diesel::table! {
+    order_returns (id) {
+        id -> Int4,
+        order_id -> Int4,
+        status -> Varchar,
+        reason -> Nullable<Varchar>,
+        resolution_note -> Nullable<Varchar>,
+        refund_amount -> Nullable<Numeric>,
+        resolved_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_status_history (id) {
+        id -> Int4,
+        order_id -> Int4,
+        from_status -> Nullable<Varchar>,
+        to_status -> Varchar,
+        changed_at -> Timestamp,
     }
 }
 
@@ -34,6 +162,44 @@ diesel::table! {
     orders_products (order_id, product_id) {
         order_id -> Int4,
         product_id -> Int4,
+        quantity -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        unit_price -> Numeric,
+        title -> Varchar,
+        author -> Varchar,
+        category_title -> Varchar,
+        returned_quantity -> Int4,
+    }
+}
+
+diesel::table! {
+    outbox_messages (id) {
+        id -> Int4,
+        job_name -> Varchar,
+        payload -> Jsonb,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        locked_until -> Nullable<Timestamp>,
+        delivered_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    payments (id) {
+        id -> Int4,
+        order_id -> Int4,
+        gateway -> Varchar,
+        payment_method -> Nullable<Varchar>,
+        reference -> Varchar,
+        status -> Varchar,
+        amount -> Numeric,
+        captured_amount -> Numeric,
+        refunded_amount -> Numeric,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -43,8 +209,37 @@ diesel::table! {
         title -> Varchar,
         category_id -> Int4,
         author -> Varchar,
-        list_price -> Float8,
+        list_price -> Numeric,
         stock_quantity -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        weight_grams -> Int4,
+    }
+}
+
+diesel::table! {
+    shipping_methods (id) {
+        id -> Int4,
+        code -> Varchar,
+        name -> Varchar,
+        base_fee -> Numeric,
+        per_item_fee -> Numeric,
+        per_kg_fee -> Numeric,
+        free_shipping_threshold -> Nullable<Numeric>,
+        is_active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_order_stats (user_id) {
+        user_id -> Int4,
+        order_count -> Int4,
+        total_spent -> Numeric,
+        last_order_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -52,20 +247,89 @@ diesel::table! {
     users (id) {
         id -> Int4,
         username -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook_subscription_id -> Int4,
+        webhook_event_id -> Int4,
+        status -> Varchar,
+        attempts -> Int4,
+        response_status -> Nullable<Int4>,
+        last_error -> Nullable<Text>,
+        delivered_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    webhook_events (id) {
+        id -> Int4,
+        event_type -> Varchar,
+        payload -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    webhook_subscriptions (id) {
+        id -> Int4,
+        url -> Varchar,
+        event_types -> Array<Text>,
+        secret -> Varchar,
+        is_active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+diesel::joinable!(addresses -> users (user_id));
+diesel::joinable!(cart_items -> products (product_id));
+diesel::joinable!(cart_items -> users (user_id));
+diesel::joinable!(idempotency_keys -> users (user_id));
+diesel::joinable!(notifications -> orders (order_id));
+diesel::joinable!(notifications -> users (user_id));
+diesel::joinable!(order_jobs -> orders (order_id));
+diesel::joinable!(order_return_items -> order_returns (order_return_id));
+diesel::joinable!(order_return_items -> products (product_id));
+diesel::joinable!(order_returns -> orders (order_id));
+diesel::joinable!(order_status_history -> orders (order_id));
 diesel::joinable!(orders -> campaigns (campaign_id));
 diesel::joinable!(orders -> users (user_id));
 diesel::joinable!(orders_products -> orders (order_id));
 diesel::joinable!(orders_products -> products (product_id));
+diesel::joinable!(payments -> orders (order_id));
 diesel::joinable!(products -> categories (category_id));
+diesel::joinable!(user_order_stats -> users (user_id));
+diesel::joinable!(webhook_deliveries -> webhook_events (webhook_event_id));
+diesel::joinable!(webhook_deliveries -> webhook_subscriptions (webhook_subscription_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    addresses,
     campaigns,
+    cart_items,
     categories,
+    dead_letter_jobs,
+    idempotency_keys,
+    notifications,
+    order_jobs,
+    order_return_items,
+    order_returns,
+    order_status_history,
     orders,
     orders_products,
+    outbox_messages,
+    payments,
     products,
+    shipping_methods,
+    user_order_stats,
     users,
+    webhook_deliveries,
+    webhook_events,
+    webhook_subscriptions,
 );